@@ -16,6 +16,7 @@ fn main() -> Result<(), minreq::Error> {
         // including the current one: this will be the rest of the
         // body if the server provided a Content-Length header, or
         // just the size of the remaining chunk in chunked transfers.
+        // See the ResponseLazy documentation for the details.
         buffer.reserve(len);
         buffer.push(byte);
 
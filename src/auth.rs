@@ -0,0 +1,285 @@
+use std::collections::HashMap;
+
+/// An authentication challenge sent by the server in a
+/// `WWW-Authenticate` header, as described in [RFC 7235 section
+/// 2.1](https://datatracker.ietf.org/doc/html/rfc7235#section-2.1).
+///
+/// Returned by
+/// [`Response::auth_challenges`](struct.Response.html#method.auth_challenges).
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct AuthChallenge {
+    /// The authentication scheme, eg. "Basic" or "Digest", as sent by
+    /// the server. Note that schemes are case-insensitive.
+    pub scheme: String,
+    /// The parameters of the challenge, eg. "realm". The parameter
+    /// names (the keys) are all lowercase, and quoted values have
+    /// been unquoted.
+    pub params: HashMap<String, String>,
+    /// The token68 of the challenge, for schemes which use one
+    /// instead of parameters.
+    pub token68: Option<String>,
+}
+
+impl AuthChallenge {
+    /// Returns true if the scheme of this challenge is `scheme`,
+    /// compared case-insensitively.
+    pub fn is_scheme(&self, scheme: &str) -> bool {
+        self.scheme.eq_ignore_ascii_case(scheme)
+    }
+}
+
+/// Parses the challenges in the value of a `WWW-Authenticate` (or
+/// `Proxy-Authenticate`) header. Malformed parts are skipped.
+pub(crate) fn parse_challenges(header: &str) -> Vec<AuthChallenge> {
+    let mut parser = Parser {
+        bytes: header.as_bytes(),
+        index: 0,
+    };
+    let mut challenges: Vec<AuthChallenge> = Vec::new();
+
+    loop {
+        parser.skip_while(|c| c == b',' || is_whitespace(c));
+        if parser.is_at_end() {
+            break;
+        }
+
+        let token = parser.take_while(is_tchar);
+        if token.is_empty() {
+            // Not the start of a scheme or a parameter, skip it.
+            parser.index += 1;
+            continue;
+        }
+
+        let after_token = parser.index;
+        parser.skip_while(is_whitespace);
+        if let (Some(challenge), Some(b'=')) = (challenges.last_mut(), parser.peek()) {
+            // A parameter of the current challenge.
+            parser.index += 1;
+            parser.skip_while(is_whitespace);
+            let value = if parser.peek() == Some(b'"') {
+                parser.take_quoted_string()
+            } else {
+                parser.take_while(is_tchar)
+            };
+            challenge.params.insert(token.to_lowercase(), value);
+            continue;
+        }
+
+        // The start of a new challenge.
+        parser.index = after_token;
+        let mut challenge = AuthChallenge {
+            scheme: token,
+            params: HashMap::new(),
+            token68: None,
+        };
+        parser.skip_while(is_whitespace);
+        let before_token68 = parser.index;
+        let token68 = parser.take_token68();
+        parser.skip_while(is_whitespace);
+        if !token68.is_empty() && matches!(parser.peek(), None | Some(b',')) {
+            challenge.token68 = Some(token68);
+        } else {
+            // Not a token68, so parse it as parameters instead.
+            parser.index = before_token68;
+        }
+        challenges.push(challenge);
+    }
+
+    challenges
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    index: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn is_at_end(&self) -> bool {
+        self.index >= self.bytes.len()
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.index).copied()
+    }
+
+    fn skip_while<F: Fn(u8) -> bool>(&mut self, f: F) {
+        while let Some(c) = self.peek() {
+            if !f(c) {
+                break;
+            }
+            self.index += 1;
+        }
+    }
+
+    fn take_while<F: Fn(u8) -> bool>(&mut self, f: F) -> String {
+        let start = self.index;
+        self.skip_while(f);
+        String::from_utf8_lossy(&self.bytes[start..self.index]).into_owned()
+    }
+
+    fn take_token68(&mut self) -> String {
+        let start = self.index;
+        self.skip_while(|c| {
+            c.is_ascii_alphanumeric() || matches!(c, b'-' | b'.' | b'_' | b'~' | b'+' | b'/')
+        });
+        self.skip_while(|c| c == b'=');
+        String::from_utf8_lossy(&self.bytes[start..self.index]).into_owned()
+    }
+
+    /// Takes a quoted string, starting at the opening quote, and
+    /// returns its unescaped contents.
+    fn take_quoted_string(&mut self) -> String {
+        let mut value = Vec::new();
+        self.index += 1;
+        while let Some(c) = self.peek() {
+            self.index += 1;
+            match c {
+                b'"' => break,
+                b'\\' => {
+                    if let Some(escaped) = self.peek() {
+                        value.push(escaped);
+                        self.index += 1;
+                    }
+                }
+                _ => value.push(c),
+            }
+        }
+        String::from_utf8_lossy(&value).into_owned()
+    }
+}
+
+fn is_whitespace(c: u8) -> bool {
+    c == b' ' || c == b'\t'
+}
+
+/// The token characters as defined in [RFC 7230 section
+/// 3.2.6](https://datatracker.ietf.org/doc/html/rfc7230#section-3.2.6).
+pub(crate) fn is_tchar(c: u8) -> bool {
+    c.is_ascii_alphanumeric()
+        || matches!(
+            c,
+            b'!' | b'#'
+                | b'$'
+                | b'%'
+                | b'&'
+                | b'\''
+                | b'*'
+                | b'+'
+                | b'-'
+                | b'.'
+                | b'^'
+                | b'_'
+                | b'`'
+                | b'|'
+                | b'~'
+        )
+}
+
+/// Encodes `input` with the standard base64 alphabet, with padding.
+pub(crate) fn base64_encode(input: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut output = String::with_capacity((input.len() + 2) / 3 * 4);
+    for chunk in input.chunks(3) {
+        let b0 = chunk[0] as usize;
+        let b1 = chunk.get(1).copied().unwrap_or(0) as usize;
+        let b2 = chunk.get(2).copied().unwrap_or(0) as usize;
+        output.push(ALPHABET[b0 >> 2] as char);
+        output.push(ALPHABET[(b0 & 0x03) << 4 | b1 >> 4] as char);
+        if chunk.len() > 1 {
+            output.push(ALPHABET[(b1 & 0x0F) << 2 | b2 >> 6] as char);
+        } else {
+            output.push('=');
+        }
+        if chunk.len() > 2 {
+            output.push(ALPHABET[b2 & 0x3F] as char);
+        } else {
+            output.push('=');
+        }
+    }
+    output
+}
+
+/// Returns the value of an `Authorization` header for Basic
+/// authentication with the given credentials.
+pub(crate) fn basic_authorization(user: &str, password: &str) -> String {
+    format!(
+        "Basic {}",
+        base64_encode(format!("{}:{}", user, password).as_bytes())
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{base64_encode, basic_authorization, parse_challenges};
+
+    #[test]
+    fn encode_base64() {
+        assert_eq!(base64_encode(b""), "");
+        assert_eq!(base64_encode(b"f"), "Zg==");
+        assert_eq!(base64_encode(b"fo"), "Zm8=");
+        assert_eq!(base64_encode(b"foo"), "Zm9v");
+        assert_eq!(base64_encode(b"foob"), "Zm9vYg==");
+        assert_eq!(base64_encode(b"fooba"), "Zm9vYmE=");
+        assert_eq!(base64_encode(b"foobar"), "Zm9vYmFy");
+        assert_eq!(base64_encode(&[0xFB, 0xFF, 0xBF]), "+/+/");
+        assert_eq!(
+            basic_authorization("Aladdin", "open sesame"),
+            "Basic QWxhZGRpbjpvcGVuIHNlc2FtZQ=="
+        );
+    }
+
+    #[test]
+    fn parse_basic() {
+        let challenges = parse_challenges(r#"Basic realm="WallyWorld", charset="UTF-8""#);
+        assert_eq!(challenges.len(), 1);
+        assert!(challenges[0].is_scheme("basic"));
+        assert_eq!(challenges[0].params["realm"], "WallyWorld");
+        assert_eq!(challenges[0].params["charset"], "UTF-8");
+        assert_eq!(challenges[0].token68, None);
+    }
+
+    #[test]
+    fn parse_digest() {
+        let challenges = parse_challenges(
+            r#"Digest
+                realm="http-auth@example.org",
+                qop="auth, auth-int",
+                algorithm=SHA-256,
+                nonce="7ypf/xlj9XXwfDPEoM4URrv/xwf94BcCAzFZH4GiTo0v",
+                opaque="FQhe/qaU925kfnzjCev0ciny7QMkPqMAFRtzCUYo5tdS""#,
+        );
+        assert_eq!(challenges.len(), 1);
+        let digest = &challenges[0];
+        assert_eq!(digest.scheme, "Digest");
+        assert_eq!(digest.params["realm"], "http-auth@example.org");
+        assert_eq!(digest.params["qop"], "auth, auth-int");
+        assert_eq!(digest.params["algorithm"], "SHA-256");
+        assert_eq!(
+            digest.params["nonce"],
+            "7ypf/xlj9XXwfDPEoM4URrv/xwf94BcCAzFZH4GiTo0v"
+        );
+        assert_eq!(
+            digest.params["opaque"],
+            "FQhe/qaU925kfnzjCev0ciny7QMkPqMAFRtzCUYo5tdS"
+        );
+    }
+
+    #[test]
+    fn parse_multiple() {
+        let challenges = parse_challenges(
+            r#"Newauth realm="apps", type=1, title="Login to \"apps\"", Basic realm="simple", Negotiate abc+/def==, Bearer"#,
+        );
+        assert_eq!(challenges.len(), 4);
+        assert_eq!(challenges[0].scheme, "Newauth");
+        assert_eq!(challenges[0].params["realm"], "apps");
+        assert_eq!(challenges[0].params["type"], "1");
+        assert_eq!(challenges[0].params["title"], "Login to \"apps\"");
+        assert_eq!(challenges[1].scheme, "Basic");
+        assert_eq!(challenges[1].params["realm"], "simple");
+        assert_eq!(challenges[2].scheme, "Negotiate");
+        assert_eq!(challenges[2].token68.as_deref(), Some("abc+/def=="));
+        assert!(challenges[2].params.is_empty());
+        assert_eq!(challenges[3].scheme, "Bearer");
+        assert!(challenges[3].params.is_empty());
+    }
+}
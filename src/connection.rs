@@ -1,10 +1,15 @@
+use crate::auth::parse_challenges;
 #[cfg(all(
     not(feature = "rustls"),
     any(feature = "openssl", feature = "native-tls")
 ))]
 use crate::native_tls::{TlsConnector, TlsStream};
-use crate::request::ParsedRequest;
-use crate::{Error, Method, ResponseLazy};
+#[cfg(feature = "proxy")]
+use crate::proxy::ProxyProtocol;
+use crate::request::{ParsedRequest, WireLogger};
+#[cfg(feature = "rustls")]
+use crate::response::TlsInfo;
+use crate::{Direction, Error, IpFamily, Method, RedirectMethodPolicy, ResponseLazy, Timings};
 #[cfg(feature = "once_cell")]
 use once_cell::sync::Lazy;
 #[cfg(feature = "rustls")]
@@ -13,7 +18,9 @@ use rustls::{self, ClientConfig, ClientConnection, RootCertStore, ServerName, St
 use std::convert::TryFrom;
 use std::env;
 use std::io::{self, Read, Write};
-use std::net::{TcpStream, ToSocketAddrs};
+#[cfg(feature = "rustls")]
+use std::net::IpAddr;
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
 #[cfg(feature = "rustls")]
 use std::sync::Arc;
 use std::time::{Duration, Instant};
@@ -44,6 +51,9 @@ static CONFIG: Lazy<Arc<ClientConfig>> = Lazy::new(|| {
         )
     }));
 
+    // Note: the default config has an in-memory session cache, and
+    // since this config is shared by every request, TLS sessions are
+    // resumed when connecting to the same server again.
     let config = ClientConfig::builder()
         .with_safe_defaults()
         .with_root_certificates(root_certificates)
@@ -60,20 +70,56 @@ type SecuredStream = StreamOwned<ClientConnection, TcpStream>;
 ))]
 type SecuredStream = TlsStream<TcpStream>;
 
-pub(crate) enum HttpStream {
-    Unsecured(UnsecuredStream, Option<Instant>),
+/// A raw connection to a server, either plain TCP or TLS, returned by
+/// [`ResponseLazy::into_stream`](struct.ResponseLazy.html#method.into_stream),
+/// or a tunnel through a proxy, returned by `Request::connect_tunnel`.
+///
+/// Reads and writes are subject to the timeout of the request that
+/// opened the connection, and are passed to its wire logger, if any.
+pub struct HttpStream {
+    inner: HttpStreamInner,
+    timeout_at: Option<Instant>,
+    headers_timeout_at: Option<Instant>,
+    pub(crate) read_call_timeout: Option<Duration>,
+    wire_logger: Option<WireLogger>,
+}
+
+enum HttpStreamInner {
+    Unsecured(UnsecuredStream),
     #[cfg(any(feature = "rustls", feature = "openssl", feature = "native-tls"))]
-    Secured(Box<SecuredStream>, Option<Instant>),
+    Secured(Box<SecuredStream>),
 }
 
 impl HttpStream {
-    fn create_unsecured(reader: UnsecuredStream, timeout_at: Option<Instant>) -> HttpStream {
-        HttpStream::Unsecured(reader, timeout_at)
+    fn create_unsecured(
+        reader: UnsecuredStream,
+        timeout_at: Option<Instant>,
+        headers_timeout_at: Option<Instant>,
+        wire_logger: Option<WireLogger>,
+    ) -> HttpStream {
+        HttpStream {
+            inner: HttpStreamInner::Unsecured(reader),
+            timeout_at,
+            headers_timeout_at,
+            read_call_timeout: None,
+            wire_logger,
+        }
     }
 
     #[cfg(any(feature = "rustls", feature = "openssl", feature = "native-tls"))]
-    fn create_secured(reader: SecuredStream, timeout_at: Option<Instant>) -> HttpStream {
-        HttpStream::Secured(Box::new(reader), timeout_at)
+    fn create_secured(
+        reader: SecuredStream,
+        timeout_at: Option<Instant>,
+        headers_timeout_at: Option<Instant>,
+        wire_logger: Option<WireLogger>,
+    ) -> HttpStream {
+        HttpStream {
+            inner: HttpStreamInner::Secured(Box::new(reader)),
+            timeout_at,
+            headers_timeout_at,
+            read_call_timeout: None,
+            wire_logger,
+        }
     }
 }
 
@@ -96,29 +142,96 @@ fn timeout_at_to_duration(timeout_at: Option<Instant>) -> Result<Option<Duration
     }
 }
 
+impl HttpStream {
+    fn tcp(&self) -> &TcpStream {
+        match &self.inner {
+            HttpStreamInner::Unsecured(inner) => inner,
+            #[cfg(any(feature = "rustls", feature = "openssl", feature = "native-tls"))]
+            HttpStreamInner::Secured(inner) => inner.get_ref(),
+        }
+    }
+
+    /// Returns the time reads should end at, i.e. the earlier of the
+    /// request timeout and the headers timeout.
+    fn read_timeout_at(&self) -> Option<Instant> {
+        match (self.timeout_at, self.headers_timeout_at) {
+            (Some(timeout_at), Some(headers_timeout_at)) => {
+                Some(timeout_at.min(headers_timeout_at))
+            }
+            (timeout_at, headers_timeout_at) => timeout_at.or(headers_timeout_at),
+        }
+    }
+
+    /// Stops applying the headers timeout to reads, called once the
+    /// headers have been read.
+    pub(crate) fn end_headers_timeout(&mut self) {
+        self.headers_timeout_at = None;
+    }
+}
+
+impl Write for HttpStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let _ = self
+            .tcp()
+            .set_write_timeout(timeout_at_to_duration(self.timeout_at)?);
+        let n = match &mut self.inner {
+            HttpStreamInner::Unsecured(inner) => inner.write(buf)?,
+            #[cfg(any(feature = "rustls", feature = "openssl", feature = "native-tls"))]
+            HttpStreamInner::Secured(inner) => inner.write(buf)?,
+        };
+        if let Some(wire_logger) = &self.wire_logger {
+            wire_logger.log(Direction::Sent, &buf[..n]);
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.inner {
+            HttpStreamInner::Unsecured(inner) => inner.flush(),
+            #[cfg(any(feature = "rustls", feature = "openssl", feature = "native-tls"))]
+            HttpStreamInner::Secured(inner) => inner.flush(),
+        }
+    }
+}
+
 impl Read for HttpStream {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        let timeout = |tcp: &TcpStream, timeout_at: Option<Instant>| -> io::Result<()> {
-            let _ = tcp.set_read_timeout(timeout_at_to_duration(timeout_at)?);
-            Ok(())
+        let deadline_timeout = timeout_at_to_duration(self.read_timeout_at())?;
+        // A zero duration would disable the timeout altogether.
+        let call_timeout = self
+            .read_call_timeout
+            .map(|timeout| timeout.max(Duration::from_millis(1)));
+        let call_timeout_first = match (call_timeout, deadline_timeout) {
+            (Some(call_timeout), Some(deadline_timeout)) => call_timeout < deadline_timeout,
+            (call_timeout, _) => call_timeout.is_some(),
         };
-
-        let result = match self {
-            HttpStream::Unsecured(inner, timeout_at) => {
-                timeout(inner, *timeout_at)?;
-                inner.read(buf)
-            }
+        let timeout = if call_timeout_first {
+            call_timeout
+        } else {
+            deadline_timeout
+        };
+        let _ = self.tcp().set_read_timeout(timeout);
+        let result = match &mut self.inner {
+            HttpStreamInner::Unsecured(inner) => inner.read(buf),
             #[cfg(any(feature = "rustls", feature = "openssl", feature = "native-tls"))]
-            HttpStream::Secured(inner, timeout_at) => {
-                timeout(inner.get_ref(), *timeout_at)?;
-                inner.read(buf)
-            }
+            HttpStreamInner::Secured(inner) => inner.read(buf),
         };
         match result {
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock && call_timeout_first => {
+                // The timeout of just this read was reached, the
+                // caller may try again.
+                Err(e)
+            }
             Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
                 // We're a blocking socket, so EWOULDBLOCK indicates a timeout
                 Err(timeout_err())
             }
+            Ok(n) => {
+                if let Some(wire_logger) = &self.wire_logger {
+                    wire_logger.log(Direction::Received, &buf[..n]);
+                }
+                Ok(n)
+            }
             r => r,
         }
     }
@@ -129,6 +242,7 @@ impl Read for HttpStream {
 pub struct Connection {
     request: ParsedRequest,
     timeout_at: Option<Instant>,
+    proxy_connect_status: Option<i32>,
 }
 
 impl Connection {
@@ -146,6 +260,7 @@ impl Connection {
         Connection {
             request,
             timeout_at,
+            proxy_connect_status: None,
         }
     }
 
@@ -159,40 +274,77 @@ impl Connection {
         timeout
     }
 
+    /// Returns the time the headers of the response should be
+    /// received by, if a headers timeout was set.
+    fn headers_timeout_at(&self, sent_at: Instant) -> Option<Instant> {
+        self.request
+            .config
+            .headers_timeout
+            .map(|timeout| sent_at + timeout)
+    }
+
     /// Sends the [`Request`](struct.Request.html), consumes this
     /// connection, and returns a [`Response`](struct.Response.html).
     #[cfg(feature = "rustls")]
     pub(crate) fn send_https(mut self) -> Result<ResponseLazy, Error> {
         enforce_timeout(self.timeout_at, move || {
             self.request.url.host = ensure_ascii_host(self.request.url.host)?;
-            let bytes = self.request.as_bytes();
 
             // Rustls setup
             log::trace!("Setting up TLS parameters for {}.", self.request.url.host);
-            let dns_name = match ServerName::try_from(&*self.request.url.host) {
-                Ok(result) => result,
-                Err(err) => return Err(Error::IoError(io::Error::new(io::ErrorKind::Other, err))),
+            let dns_name = server_name(&self.request.url.host)?;
+            let config = match &self.request.config.rustls_config {
+                Some(config) => config.0.clone(),
+                None => CONFIG.clone(),
             };
-            let sess = ClientConnection::new(CONFIG.clone(), dns_name)
-                .map_err(Error::RustlsCreateConnection)?;
+            let sess =
+                ClientConnection::new(config, dns_name).map_err(Error::RustlsCreateConnection)?;
 
             log::trace!("Establishing TCP connection to {}.", self.request.url.host);
-            let tcp = self.connect()?;
+            let mut timings = Timings::default();
+            let tcp = self.connect(&mut timings)?;
+            let used_ipv6 = is_ipv6(&tcp);
 
             // Send request
             log::trace!("Establishing TLS session to {}.", self.request.url.host);
             let mut tls = StreamOwned::new(sess, tcp); // I don't think this actually does any communication.
-            log::trace!("Writing HTTPS request to {}.", self.request.url.host);
             let _ = tls.get_ref().set_write_timeout(self.timeout()?);
-            tls.write_all(&bytes)?;
+            let handshake_start = Instant::now();
+            tls.conn.complete_io(&mut tls.sock)?;
+            timings.tls_handshake = Some(handshake_start.elapsed());
+            log::trace!("Writing HTTPS request to {}.", self.request.url.host);
+            self.request.write_to(&mut tls)?;
+            let sent_at = Instant::now();
+            let tls_info = tls_info(&tls.conn);
 
             // Receive request
             log::trace!("Reading HTTPS response from {}.", self.request.url.host);
-            let response = ResponseLazy::from_stream(
-                HttpStream::create_secured(tls, self.timeout_at),
+            let mut response = ResponseLazy::from_stream(
+                HttpStream::create_secured(
+                    tls,
+                    self.timeout_at,
+                    self.headers_timeout_at(sent_at),
+                    self.request.config.wire_logger.clone(),
+                ),
                 self.request.config.max_headers_size,
+                self.request.config.max_trailer_size,
                 self.request.config.max_status_line_len,
+                self.request.config.max_response_size,
+                self.request.config.strict_parsing,
+                self.request.config.force_close_delimited,
+                self.request.config.method == Method::Head,
             )?;
+            timings.first_byte = sent_at.elapsed();
+            response.tls_info = tls_info;
+            response.timings = timings;
+            response.proxy_connect_status = self.proxy_connect_status;
+            response.proxy_source = self.request.proxy_source;
+            response.used_ipv6 = used_ipv6;
+            response.extensions = self.request.config.extensions.clone();
+            #[cfg(feature = "decompress")]
+            if !self.request.config.auto_decompression {
+                response.decoder = None;
+            }
             handle_redirects(self, response)
         })
     }
@@ -206,10 +358,9 @@ impl Connection {
     pub(crate) fn send_https(mut self) -> Result<ResponseLazy, Error> {
         enforce_timeout(self.timeout_at, move || {
             self.request.url.host = ensure_ascii_host(self.request.url.host)?;
-            let bytes = self.request.as_bytes();
 
             log::trace!("Setting up TLS parameters for {}.", self.request.url.host);
-            let dns_name = &self.request.url.host;
+            let dns_name = self.request.url.host.clone();
             /*
             let mut builder = TlsConnector::builder();
             ...
@@ -221,25 +372,50 @@ impl Connection {
             };
 
             log::trace!("Establishing TCP connection to {}.", self.request.url.host);
-            let tcp = self.connect()?;
+            let mut timings = Timings::default();
+            let tcp = self.connect(&mut timings)?;
+            let used_ipv6 = is_ipv6(&tcp);
 
             // Send request
             log::trace!("Establishing TLS session to {}.", self.request.url.host);
-            let mut tls = match sess.connect(dns_name, tcp) {
+            let handshake_start = Instant::now();
+            let mut tls = match sess.connect(&dns_name, tcp) {
                 Ok(tls) => tls,
                 Err(err) => return Err(Error::IoError(io::Error::new(io::ErrorKind::Other, err))),
             };
+            timings.tls_handshake = Some(handshake_start.elapsed());
             log::trace!("Writing HTTPS request to {}.", self.request.url.host);
             let _ = tls.get_ref().set_write_timeout(self.timeout()?);
-            tls.write_all(&bytes)?;
+            self.request.write_to(&mut tls)?;
+            let sent_at = Instant::now();
 
             // Receive request
             log::trace!("Reading HTTPS response from {}.", self.request.url.host);
-            let response = ResponseLazy::from_stream(
-                HttpStream::create_secured(tls, self.timeout_at),
+            let mut response = ResponseLazy::from_stream(
+                HttpStream::create_secured(
+                    tls,
+                    self.timeout_at,
+                    self.headers_timeout_at(sent_at),
+                    self.request.config.wire_logger.clone(),
+                ),
                 self.request.config.max_headers_size,
+                self.request.config.max_trailer_size,
                 self.request.config.max_status_line_len,
+                self.request.config.max_response_size,
+                self.request.config.strict_parsing,
+                self.request.config.force_close_delimited,
+                self.request.config.method == Method::Head,
             )?;
+            timings.first_byte = sent_at.elapsed();
+            response.timings = timings;
+            response.proxy_connect_status = self.proxy_connect_status;
+            response.proxy_source = self.request.proxy_source;
+            response.used_ipv6 = used_ipv6;
+            response.extensions = self.request.config.extensions.clone();
+            #[cfg(feature = "decompress")]
+            if !self.request.config.auto_decompression {
+                response.decoder = None;
+            }
             handle_redirects(self, response)
         })
     }
@@ -249,80 +425,248 @@ impl Connection {
     pub(crate) fn send(mut self) -> Result<ResponseLazy, Error> {
         enforce_timeout(self.timeout_at, move || {
             self.request.url.host = ensure_ascii_host(self.request.url.host)?;
-            let bytes = self.request.as_bytes();
 
             log::trace!("Establishing TCP connection to {}.", self.request.url.host);
-            let mut tcp = self.connect()?;
+            let mut timings = Timings::default();
+            let mut tcp = self.connect(&mut timings)?;
+            let used_ipv6 = is_ipv6(&tcp);
 
             // Send request
             log::trace!("Writing HTTP request.");
             let _ = tcp.set_write_timeout(self.timeout()?);
-            tcp.write_all(&bytes)?;
+            self.request.write_to(&mut tcp)?;
+            let sent_at = Instant::now();
 
             // Receive response
             log::trace!("Reading HTTP response.");
-            let stream = HttpStream::create_unsecured(tcp, self.timeout_at);
-            let response = ResponseLazy::from_stream(
+            let stream = HttpStream::create_unsecured(
+                tcp,
+                self.timeout_at,
+                self.headers_timeout_at(sent_at),
+                self.request.config.wire_logger.clone(),
+            );
+            let mut response = ResponseLazy::from_stream(
                 stream,
                 self.request.config.max_headers_size,
+                self.request.config.max_trailer_size,
                 self.request.config.max_status_line_len,
+                self.request.config.max_response_size,
+                self.request.config.strict_parsing,
+                self.request.config.force_close_delimited,
+                self.request.config.method == Method::Head,
             )?;
+            timings.first_byte = sent_at.elapsed();
+            response.timings = timings;
+            response.proxy_connect_status = self.proxy_connect_status;
+            response.proxy_source = self.request.proxy_source;
+            response.used_ipv6 = used_ipv6;
+            response.extensions = self.request.config.extensions.clone();
+            #[cfg(feature = "decompress")]
+            if !self.request.config.auto_decompression {
+                response.decoder = None;
+            }
             handle_redirects(self, response)
         })
     }
 
-    fn connect(&self) -> Result<TcpStream, Error> {
-        let tcp_connect = |host: &str, port: u32| -> Result<TcpStream, Error> {
-            let addrs = (host, port as u16)
-                .to_socket_addrs()
-                .map_err(Error::IoError)?;
-            let addrs_count = addrs.len();
-
-            // Try all resolved addresses. Return the first one to which we could connect. If all
-            // failed return the last error encountered.
-            for (i, addr) in addrs.enumerate() {
-                let stream = if let Some(timeout) = self.timeout()? {
-                    TcpStream::connect_timeout(&addr, timeout)
-                } else {
-                    TcpStream::connect(addr)
-                };
-                if stream.is_ok() || i == addrs_count - 1 {
-                    return stream.map_err(Error::from);
-                }
+    /// Opens a tunnel to the host of the request through the proxy,
+    /// and returns it as-is, without sending the request.
+    #[cfg(feature = "proxy")]
+    pub(crate) fn tunnel(mut self) -> Result<HttpStream, Error> {
+        enforce_timeout(self.timeout_at, move || {
+            self.request.url.host = ensure_ascii_host(self.request.url.host)?;
+            let proxy = self.request.config.proxy.clone().ok_or(Error::NoProxy)?;
+
+            log::trace!("Establishing tunnel to {}.", self.request.url.host);
+            let tcp = self.connect_through_proxy(&proxy, &mut Timings::default())?;
+            Ok(HttpStream::create_unsecured(
+                tcp,
+                self.timeout_at,
+                None,
+                self.request.config.wire_logger.clone(),
+            ))
+        })
+    }
+
+    fn connect(&mut self, timings: &mut Timings) -> Result<TcpStream, Error> {
+        #[cfg(feature = "proxy")]
+        match self.request.config.proxy.clone() {
+            Some(proxy) if !self.request.url.https && !proxy.is_socks5() => {
+                // Plain HTTP requests are sent to the proxy as-is, with
+                // an absolute-form request target, see get_http_head.
+                self.tcp_connect(&proxy.server, proxy.port, timings)
             }
+            Some(proxy) => self.connect_through_proxy(&proxy, timings),
+            None => self.tcp_connect(
+                &self.request.url.host,
+                self.request.url.port.port(),
+                timings,
+            ),
+        }
 
-            Err(Error::AddressNotFound)
+        #[cfg(not(feature = "proxy"))]
+        self.tcp_connect(
+            &self.request.url.host,
+            self.request.url.port.port(),
+            timings,
+        )
+    }
+
+    fn tcp_connect(
+        &self,
+        host: &str,
+        port: u32,
+        timings: &mut Timings,
+    ) -> Result<TcpStream, Error> {
+        let dns_start = Instant::now();
+        let addrs = self.resolve(host, port)?;
+        timings.dns_lookup = dns_start.elapsed();
+        let connect_start = Instant::now();
+        let tcp = connect_to_any(addrs.into_iter(), self.timeout_at)?;
+        if let Some(socket_config) = &self.request.config.socket_config {
+            socket_config.configure(&tcp);
+        }
+        timings.connect = connect_start.elapsed();
+        Ok(tcp)
+    }
+
+    /// Resolves the addresses of the host, with the resolver set for
+    /// the request if any, keeping only the ones of the requested IP
+    /// family.
+    fn resolve(&self, host: &str, port: u32) -> Result<Vec<SocketAddr>, Error> {
+        let addrs = match &self.request.config.resolver {
+            Some(resolver) => resolver.resolve(host, port as u16),
+            None => (host, port as u16)
+                .to_socket_addrs()
+                .map(|addrs| addrs.collect()),
         };
+        let addrs = addrs.map_err(Error::DnsFailure)?;
+        Ok(match self.request.config.ip_family {
+            Some(ip_family) => filter_ip_family(addrs, ip_family),
+            None => addrs,
+        })
+    }
 
-        #[cfg(feature = "proxy")]
-        match self.request.config.proxy {
-            Some(ref proxy) => {
-                // do proxy things
-                let mut tcp = tcp_connect(&proxy.server, proxy.port)?;
-
-                write!(tcp, "{}", proxy.connect(&self.request)).unwrap();
-                tcp.flush()?;
-
-                let mut proxy_response = Vec::new();
-
-                loop {
-                    let mut buf = vec![0; 256];
-                    let total = tcp.read(&mut buf)?;
-                    proxy_response.append(&mut buf);
-                    if total < 256 {
-                        break;
-                    }
-                }
+    /// Connects to the proxy, and asks it to open a tunnel to the
+    /// host of the request, with `CONNECT` or the SOCKS5 handshake.
+    #[cfg(feature = "proxy")]
+    fn connect_through_proxy(
+        &mut self,
+        proxy: &crate::Proxy,
+        timings: &mut Timings,
+    ) -> Result<TcpStream, Error> {
+        let mut tcp = self.tcp_connect(&proxy.server, proxy.port, timings)?;
+        let tunnel_start = Instant::now();
+
+        // The enforce_timeout thread gives up on the request,
+        // but the socket needs its own timeouts to not leave
+        // this thread stuck on a proxy that never responds.
+        let _ = tcp.set_write_timeout(self.timeout()?);
+        let _ = tcp.set_read_timeout(self.timeout()?);
+
+        if let ProxyProtocol::Socks5 { remote_dns } = proxy.protocol {
+            let host = &self.request.url.host;
+            let port = self.request.url.port.port();
+            let address = if remote_dns {
+                None
+            } else {
+                let addrs = self.resolve(host, port)?;
+                Some(addrs.first().ok_or(Error::AddressNotFound)?.ip())
+            };
+            proxy.socks5_handshake(&mut tcp, host, port as u16, address)?;
+            timings.connect += tunnel_start.elapsed();
+            return Ok(tcp);
+        }
+
+        write!(tcp, "{}", proxy.connect(&self.request))?;
+        tcp.flush()?;
 
-                crate::Proxy::verify_response(&proxy_response)?;
+        let mut proxy_response = Vec::new();
 
-                Ok(tcp)
+        loop {
+            let mut buf = vec![0; 256];
+            let total = tcp.read(&mut buf)?;
+            proxy_response.append(&mut buf);
+            if total < 256 {
+                break;
             }
-            None => tcp_connect(&self.request.url.host, self.request.url.port.port()),
         }
 
-        #[cfg(not(feature = "proxy"))]
-        tcp_connect(&self.request.url.host, self.request.url.port.port())
+        let status_code = crate::Proxy::verify_response(&proxy_response)?;
+        self.proxy_connect_status = Some(status_code);
+        timings.connect += tunnel_start.elapsed();
+
+        Ok(tcp)
+    }
+}
+
+/// Returns true if the stream is connected to an IPv6 address.
+fn is_ipv6(tcp: &TcpStream) -> bool {
+    tcp.peer_addr().map_or(false, |addr| addr.is_ipv6())
+}
+
+/// Returns the name the server's certificate is verified against. IP
+/// literals are checked first, so they're always used as an IP
+/// address, and never mistaken for a DNS name.
+#[cfg(feature = "rustls")]
+fn server_name(host: &str) -> Result<ServerName, Error> {
+    if let Ok(ip) = host.parse::<IpAddr>() {
+        return Ok(ServerName::IpAddress(ip));
+    }
+    match ServerName::try_from(host) {
+        Ok(result) => Ok(result),
+        Err(err) => Err(Error::IoError(io::Error::new(io::ErrorKind::Other, err))),
+    }
+}
+
+/// Returns the negotiated parameters of the TLS connection, if the
+/// handshake has been completed.
+#[cfg(feature = "rustls")]
+fn tls_info(connection: &ClientConnection) -> Option<TlsInfo> {
+    let cipher_suite = connection.negotiated_cipher_suite()?;
+    let protocol_version = connection.protocol_version()?;
+    Some(TlsInfo {
+        cipher_suite: format!("{:?}", cipher_suite.suite()),
+        protocol_version: format!("{:?}", protocol_version),
+    })
+}
+
+/// Filters out and reorders the addresses according to `ip_family`.
+/// The order within a family is preserved.
+fn filter_ip_family(mut addrs: Vec<SocketAddr>, ip_family: IpFamily) -> Vec<SocketAddr> {
+    match ip_family {
+        IpFamily::V4Only => addrs.retain(SocketAddr::is_ipv4),
+        IpFamily::V6Only => addrs.retain(SocketAddr::is_ipv6),
+        IpFamily::PreferV4 => addrs.sort_by_key(SocketAddr::is_ipv6),
+        IpFamily::PreferV6 => addrs.sort_by_key(SocketAddr::is_ipv4),
+    }
+    addrs
+}
+
+/// Tries to connect to each of the addresses in order, returning the
+/// first successful connection. If all of them fail, the last error
+/// encountered is returned, and if there were no addresses to try,
+/// [Error::AddressNotFound] is returned.
+fn connect_to_any<I>(addrs: I, timeout_at: Option<Instant>) -> Result<TcpStream, Error>
+where
+    I: Iterator<Item = SocketAddr>,
+{
+    let mut last_error = None;
+    for addr in addrs {
+        let stream = if let Some(timeout) = timeout_at_to_duration(timeout_at)? {
+            TcpStream::connect_timeout(&addr, timeout)
+        } else {
+            TcpStream::connect(addr)
+        };
+        match stream {
+            Ok(stream) => return Ok(stream),
+            Err(err) => last_error = Some(err),
+        }
+    }
+
+    match last_error {
+        Some(err) => Err(Error::IoError(err)),
+        None => Err(Error::AddressNotFound),
     }
 }
 
@@ -331,25 +675,31 @@ fn handle_redirects(
     mut response: ResponseLazy,
 ) -> Result<ResponseLazy, Error> {
     let status_code = response.status_code;
+    if status_code == 401 && connection.request.can_retry_with_auth() {
+        let offers_basic = response
+            .headers
+            .get("www-authenticate")
+            .map_or(false, |header| {
+                let challenges = parse_challenges(header);
+                challenges
+                    .iter()
+                    .any(|challenge| challenge.is_scheme("basic"))
+            });
+        if offers_basic {
+            log::debug!("Retrying with Basic authentication");
+            let mut connection = connection;
+            connection.request.auth_retried = true;
+            return resend(connection);
+        }
+    }
+
     let url = response.headers.get("location");
     match get_redirect(connection, status_code, url) {
-        NextHop::Redirect(connection) => {
-            let connection = connection?;
-            if connection.request.url.https {
-                #[cfg(not(any(
-                    feature = "rustls",
-                    feature = "openssl",
-                    feature = "native-tls"
-                )))]
-                return Err(Error::HttpsFeatureNotEnabled);
-                #[cfg(any(feature = "rustls", feature = "openssl", feature = "native-tls"))]
-                return connection.send_https();
-            } else {
-                connection.send()
-            }
-        }
+        NextHop::Redirect(connection) => resend(connection?),
         NextHop::Destination(connection) => {
+            response.redirect_count = connection.request.redirects.len();
             let dst_url = connection.request.url;
+            // Writing into a String can't fail.
             dst_url.write_base_url_to(&mut response.url).unwrap();
             dst_url.write_resource_to(&mut response.url).unwrap();
             Ok(response)
@@ -357,6 +707,19 @@ fn handle_redirects(
     }
 }
 
+/// Sends the (modified) request of `connection` again, over a new
+/// connection.
+fn resend(connection: Connection) -> Result<ResponseLazy, Error> {
+    if connection.request.url.https {
+        #[cfg(not(any(feature = "rustls", feature = "openssl", feature = "native-tls")))]
+        return Err(Error::HttpsFeatureNotEnabled);
+        #[cfg(any(feature = "rustls", feature = "openssl", feature = "native-tls"))]
+        return connection.send_https();
+    } else {
+        connection.send()
+    }
+}
+
 enum NextHop {
     Redirect(Result<Connection, Error>),
     Destination(Connection),
@@ -364,22 +727,37 @@ enum NextHop {
 
 fn get_redirect(mut connection: Connection, status_code: i32, url: Option<&String>) -> NextHop {
     match status_code {
-        301 | 302 | 303 | 307 => {
+        301 | 302 | 303 | 307 | 308 => {
             let url = match url {
                 Some(url) => url,
+                None if connection.request.config.lenient_redirects => {
+                    return NextHop::Destination(connection)
+                }
                 None => return NextHop::Redirect(Err(Error::RedirectLocationMissing)),
             };
+            if connection.request.config.same_origin_redirects
+                && connection.request.redirect_leaves_origin(url)
+            {
+                log::debug!("Not following redirection ({}) to: {}", status_code, url);
+                return NextHop::Destination(connection);
+            }
             log::debug!("Redirecting ({}) to: {}", status_code, url);
 
             match connection.request.redirect_to(url.as_str()) {
                 Ok(()) => {
+                    let config = &mut connection.request.config;
                     if status_code == 303 {
-                        match connection.request.config.method {
+                        match config.method {
                             Method::Post | Method::Put | Method::Delete => {
-                                connection.request.config.method = Method::Get;
+                                config.method = Method::Get;
                             }
                             _ => {}
                         }
+                    } else if (status_code == 301 || status_code == 302)
+                        && config.method == Method::Post
+                        && config.redirect_method_policy == RedirectMethodPolicy::PostToGet
+                    {
+                        config.method = Method::Get;
                     }
 
                     NextHop::Redirect(Ok(connection))
@@ -391,7 +769,7 @@ fn get_redirect(mut connection: Connection, status_code: i32, url: Option<&Strin
     }
 }
 
-fn ensure_ascii_host(host: String) -> Result<String, Error> {
+pub(crate) fn ensure_ascii_host(host: String) -> Result<String, Error> {
     if host.is_ascii() {
         Ok(host)
     } else {
@@ -426,6 +804,10 @@ fn ensure_ascii_host(host: String) -> Result<String, Error> {
 /// While minreq does use timeouts (somewhat) properly, some
 /// interfaces such as [ToSocketAddrs] don't allow for specifying the
 /// timeout. Hence this.
+///
+/// If `f` panics on the spawned thread, an [Error::Other] is returned.
+/// Without a timeout, `f` runs on the current thread, and a panic in
+/// it unwinds into the caller as usual.
 fn enforce_timeout<F, R>(timeout_at: Option<Instant>, f: F) -> Result<R, Error>
 where
     F: 'static + Send + FnOnce() -> Result<R, Error>,
@@ -433,28 +815,148 @@ where
 {
     use std::sync::mpsc::{channel, RecvTimeoutError};
 
+    let f = move || f().map_err(classify_error);
     match timeout_at {
         Some(deadline) => {
             let (sender, receiver) = channel();
-            let thread = std::thread::spawn(move || {
-                let result = f();
-                let _ = sender.send(());
-                result
+            std::thread::spawn(move || {
+                let _ = sender.send(f());
             });
             if let Some(timeout_duration) = deadline.checked_duration_since(Instant::now()) {
                 match receiver.recv_timeout(timeout_duration) {
-                    Ok(()) => thread.join().unwrap(),
+                    Ok(result) => result,
                     Err(err) => match err {
-                        RecvTimeoutError::Timeout => Err(Error::IoError(timeout_err())),
+                        RecvTimeoutError::Timeout => Err(Error::Timeout),
+                        // A panic drops the sender without sending anything.
                         RecvTimeoutError::Disconnected => {
                             Err(Error::Other("request connection paniced"))
                         }
                     },
                 }
             } else {
-                Err(Error::IoError(timeout_err()))
+                Err(Error::Timeout)
             }
         }
         None => f(),
     }
 }
+
+/// Turns the io errors which have their own variants in [Error] into
+/// them.
+fn classify_error(err: Error) -> Error {
+    match err {
+        Error::IoError(err) => match err.kind() {
+            io::ErrorKind::TimedOut => Error::Timeout,
+            io::ErrorKind::ConnectionRefused => Error::ConnectionRefused,
+            _ => Error::IoError(err),
+        },
+        err => err,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{connect_to_any, enforce_timeout, filter_ip_family, Connection};
+    use crate::request::ParsedRequest;
+    use crate::{get, Error, IpFamily};
+    use std::io::{Read, Write};
+    use std::net::{SocketAddr, TcpListener};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn ip_family_filtering() {
+        let v4a: SocketAddr = "127.0.0.1:80".parse().unwrap();
+        let v4b: SocketAddr = "127.0.0.2:80".parse().unwrap();
+        let v6a: SocketAddr = "[::1]:80".parse().unwrap();
+        let v6b: SocketAddr = "[::2]:80".parse().unwrap();
+        let addrs = vec![v6a, v4a, v6b, v4b];
+        let filter = |ip_family| filter_ip_family(addrs.clone(), ip_family);
+        assert_eq!(filter(IpFamily::V4Only), vec![v4a, v4b]);
+        assert_eq!(filter(IpFamily::V6Only), vec![v6a, v6b]);
+        assert_eq!(filter(IpFamily::PreferV4), vec![v4a, v4b, v6a, v6b]);
+        assert_eq!(filter(IpFamily::PreferV6), vec![v6a, v6b, v4a, v4b]);
+    }
+
+    #[test]
+    fn panic_in_timeout_thread() {
+        let timeout_at = Some(Instant::now() + Duration::from_secs(5));
+        let result: Result<(), Error> = enforce_timeout(timeout_at, || panic!("oh no"));
+        assert!(matches!(result, Err(Error::Other(_))));
+
+        let result = enforce_timeout(timeout_at, || Ok(1));
+        assert!(matches!(result, Ok(1)));
+    }
+
+    #[test]
+    fn connect_with_no_addresses() {
+        let result = connect_to_any(std::iter::empty(), None);
+        assert!(matches!(result, Err(Error::AddressNotFound)));
+    }
+
+    #[test]
+    fn connect_falls_through_to_later_addresses() {
+        // Bind and drop a listener to get a port that (most likely)
+        // refuses connections.
+        let closed_addr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let open_addr = listener.local_addr().unwrap();
+
+        let addrs: Vec<SocketAddr> = vec![closed_addr, open_addr];
+        let stream = connect_to_any(addrs.into_iter(), None).unwrap();
+        assert_eq!(stream.peer_addr().unwrap(), open_addr);
+
+        let addrs: Vec<SocketAddr> = vec![closed_addr];
+        let result = connect_to_any(addrs.into_iter(), None);
+        assert!(matches!(result, Err(Error::IoError(_))));
+    }
+
+    #[test]
+    fn used_ipv6() {
+        fn serve(listener: TcpListener) -> u16 {
+            let port = listener.local_addr().unwrap().port();
+            thread::spawn(move || {
+                let mut stream = listener.accept().unwrap().0;
+                let _ = stream.read(&mut [0; 1024]);
+                let _ = stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n");
+            });
+            port
+        }
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://127.0.0.1:{}/", serve(listener));
+        let request = ParsedRequest::new(get(&url)).unwrap();
+        let response = Connection::new(request).send().unwrap();
+        assert!(!response.used_ipv6());
+
+        let listener = match TcpListener::bind("[::1]:0") {
+            Ok(listener) => listener,
+            // IPv6 is not available, e.g. in some CI environments.
+            Err(_) => return,
+        };
+        let url = format!("http://[::1]:{}/", serve(listener));
+        let request = ParsedRequest::new(get(&url)).unwrap();
+        let response = Connection::new(request).send().unwrap();
+        assert!(response.used_ipv6());
+    }
+
+    #[test]
+    #[cfg(feature = "rustls")]
+    fn server_name_for_ip_literals() {
+        use super::server_name;
+        use rustls::ServerName;
+        use std::net::IpAddr;
+
+        let ipv4 = "127.0.0.1".parse::<IpAddr>().unwrap();
+        assert!(matches!(server_name("127.0.0.1"), Ok(ServerName::IpAddress(ip)) if ip == ipv4));
+        let ipv6 = "::1".parse::<IpAddr>().unwrap();
+        assert!(matches!(server_name("::1"), Ok(ServerName::IpAddress(ip)) if ip == ipv6));
+        assert!(matches!(
+            server_name("example.com"),
+            Ok(ServerName::DnsName(_))
+        ));
+    }
+}
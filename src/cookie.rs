@@ -0,0 +1,135 @@
+/// A cookie set by the server with a `Set-Cookie` header, as
+/// described in [RFC 6265 section
+/// 4.1](https://datatracker.ietf.org/doc/html/rfc6265#section-4.1).
+///
+/// Returned by
+/// [`Response::cookies`](struct.Response.html#method.cookies).
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Cookie {
+    /// The name of the cookie.
+    pub name: String,
+    /// The value of the cookie.
+    pub value: String,
+    /// The `Domain` attribute, lowercase and without a leading dot.
+    pub domain: Option<String>,
+    /// The `Path` attribute. Only set if it starts with a `/`.
+    pub path: Option<String>,
+    /// The `Expires` attribute, as sent by the server.
+    pub expires: Option<String>,
+    /// The `Max-Age` attribute, in seconds.
+    pub max_age: Option<i64>,
+    /// Whether the `Secure` attribute was present.
+    pub secure: bool,
+    /// Whether the `HttpOnly` attribute was present.
+    pub http_only: bool,
+    /// The `SameSite` attribute, as sent by the server.
+    pub same_site: Option<String>,
+}
+
+/// Parses the value of a `Set-Cookie` header, following the algorithm
+/// in [RFC 6265 section
+/// 5.2](https://datatracker.ietf.org/doc/html/rfc6265#section-5.2).
+/// Returns `None` if the cookie should be ignored.
+pub(crate) fn parse_set_cookie(header: &str) -> Option<Cookie> {
+    let mut parts = header.split(';');
+    let name_value_pair = parts.next()?;
+    let equals = name_value_pair.find('=')?;
+    let name = name_value_pair[..equals].trim();
+    if name.is_empty() {
+        return None;
+    }
+
+    let mut cookie = Cookie {
+        name: name.to_string(),
+        value: name_value_pair[equals + 1..].trim().to_string(),
+        domain: None,
+        path: None,
+        expires: None,
+        max_age: None,
+        secure: false,
+        http_only: false,
+        same_site: None,
+    };
+
+    for attribute in parts {
+        let (name, value) = match attribute.find('=') {
+            Some(equals) => (&attribute[..equals], &attribute[equals + 1..]),
+            None => (attribute, ""),
+        };
+        let value = value.trim();
+        match name.trim().to_ascii_lowercase().as_str() {
+            "expires" => cookie.expires = Some(value.to_string()),
+            "max-age" => {
+                let digits = value.strip_prefix('-').unwrap_or(value);
+                if !digits.is_empty() && digits.bytes().all(|c| c.is_ascii_digit()) {
+                    cookie.max_age = value.parse().ok();
+                }
+            }
+            "domain" if !value.is_empty() => {
+                let domain = value.strip_prefix('.').unwrap_or(value);
+                cookie.domain = Some(domain.to_ascii_lowercase());
+            }
+            "path" if value.starts_with('/') => cookie.path = Some(value.to_string()),
+            "secure" => cookie.secure = true,
+            "httponly" => cookie.http_only = true,
+            "samesite" => cookie.same_site = Some(value.to_string()),
+            _ => {}
+        }
+    }
+
+    Some(cookie)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_set_cookie;
+
+    #[test]
+    fn parse_simple() {
+        let cookie = parse_set_cookie("SID=31d4d96e407aad42").unwrap();
+        assert_eq!(cookie.name, "SID");
+        assert_eq!(cookie.value, "31d4d96e407aad42");
+        assert_eq!(cookie.domain, None);
+        assert_eq!(cookie.path, None);
+        assert!(!cookie.secure);
+        assert!(!cookie.http_only);
+    }
+
+    #[test]
+    fn parse_attributes() {
+        let cookie = parse_set_cookie(
+            "SID=31d4d96e407aad42; Path=/; Domain=.Example.com; Secure; HttpOnly; \
+             Expires=Wed, 09 Jun 2021 10:18:14 GMT; Max-Age=3600; SameSite=Lax",
+        )
+        .unwrap();
+        assert_eq!(cookie.name, "SID");
+        assert_eq!(cookie.value, "31d4d96e407aad42");
+        assert_eq!(cookie.domain.as_deref(), Some("example.com"));
+        assert_eq!(cookie.path.as_deref(), Some("/"));
+        assert_eq!(
+            cookie.expires.as_deref(),
+            Some("Wed, 09 Jun 2021 10:18:14 GMT")
+        );
+        assert_eq!(cookie.max_age, Some(3600));
+        assert!(cookie.secure);
+        assert!(cookie.http_only);
+        assert_eq!(cookie.same_site.as_deref(), Some("Lax"));
+    }
+
+    #[test]
+    fn parse_edge_cases() {
+        // Empty values and quotes are kept as-is.
+        let cookie = parse_set_cookie(" lang = ; max-age=-1").unwrap();
+        assert_eq!(cookie.name, "lang");
+        assert_eq!(cookie.value, "");
+        assert_eq!(cookie.max_age, Some(-1));
+        let cookie = parse_set_cookie("q=\"a=b\"; path=relative; max-age=1a").unwrap();
+        assert_eq!(cookie.value, "\"a=b\"");
+        assert_eq!(cookie.path, None);
+        assert_eq!(cookie.max_age, None);
+
+        // Cookies without a name or a '=' are ignored.
+        assert_eq!(parse_set_cookie("=value"), None);
+        assert_eq!(parse_set_cookie("novalue; Secure"), None);
+    }
+}
@@ -0,0 +1,149 @@
+//! Decoding of gzip and deflate compressed response bodies, for the
+//! `decompress` feature.
+
+use flate2::write::MultiGzDecoder;
+use flate2::{Decompress, FlushDecompress, Status};
+use std::io::{self, Write};
+use std::mem;
+
+/// How much more space to make for the decoded bytes at a time, when
+/// decoding deflate.
+const DEFLATE_OUTPUT_RESERVE: usize = 32 * 1024;
+
+enum Coding {
+    Gzip(MultiGzDecoder<Vec<u8>>),
+    // flate2's deflate writers don't report streams that were cut
+    // short, so deflate is decoded with the lower level API, which
+    // tells when the end of the stream has been reached.
+    Deflate {
+        decompress: Decompress,
+        decoded: Vec<u8>,
+        ended: bool,
+    },
+}
+
+/// Decodes a compressed body as it's read: the compressed bytes are
+/// written in, and the decoded bytes are buffered until they're
+/// taken out with [`Decoder::next_byte`].
+pub(crate) struct Decoder {
+    coding: Coding,
+    output: Vec<u8>,
+    index: usize,
+    received_any: bool,
+    finished: bool,
+    /// The amount of decoded bytes taken out so far.
+    pub(crate) bytes_out: usize,
+}
+
+impl Decoder {
+    /// Returns a decoder for the given `Content-Encoding`, or `None`
+    /// if it isn't gzip or deflate. Bodies with multiple encodings
+    /// applied aren't decoded.
+    pub(crate) fn new(content_encoding: &str) -> Option<Decoder> {
+        let coding = match content_encoding.trim().to_ascii_lowercase().as_str() {
+            "gzip" | "x-gzip" => Coding::Gzip(MultiGzDecoder::new(Vec::new())),
+            // The "deflate" coding is actually the zlib format:
+            // https://datatracker.ietf.org/doc/html/rfc9110#section-8.4.1.2
+            "deflate" => Coding::Deflate {
+                decompress: Decompress::new(true),
+                decoded: Vec::new(),
+                ended: false,
+            },
+            _ => return None,
+        };
+        Some(Decoder {
+            coding,
+            output: Vec::new(),
+            index: 0,
+            received_any: false,
+            finished: false,
+            bytes_out: 0,
+        })
+    }
+
+    /// Returns the next decoded byte, along with the amount of decoded
+    /// bytes currently buffered, or `None` if more compressed bytes
+    /// need to be written first.
+    pub(crate) fn next_byte(&mut self) -> Option<(u8, usize)> {
+        let byte = *self.output.get(self.index)?;
+        let buffered = self.output.len() - self.index;
+        self.index += 1;
+        self.bytes_out += 1;
+        Some((byte, buffered))
+    }
+
+    /// Returns true if the whole body has been decoded with
+    /// [`Decoder::finish`].
+    pub(crate) fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Decodes the given compressed bytes. Should only be called
+    /// after the previously decoded bytes have been taken out.
+    pub(crate) fn write(&mut self, compressed: &[u8]) -> io::Result<()> {
+        self.received_any |= !compressed.is_empty();
+        match &mut self.coding {
+            Coding::Gzip(decoder) => decoder.write_all(compressed)?,
+            Coding::Deflate {
+                decompress,
+                decoded,
+                ended,
+            } => {
+                let mut input = compressed;
+                // Anything after the end of the stream is ignored.
+                while !input.is_empty() && !*ended {
+                    decoded.reserve(DEFLATE_OUTPUT_RESERVE);
+                    let (total_in, total_out) = (decompress.total_in(), decompress.total_out());
+                    let status =
+                        decompress.decompress_vec(input, decoded, FlushDecompress::None)?;
+                    let consumed = (decompress.total_in() - total_in) as usize;
+                    if consumed == 0 && decompress.total_out() == total_out {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            "corrupt deflate stream",
+                        ));
+                    }
+                    input = &input[consumed..];
+                    *ended = status == Status::StreamEnd;
+                }
+            }
+        }
+        self.take_output();
+        Ok(())
+    }
+
+    /// Decodes the rest of the body after all of it has been written,
+    /// returning an error if the compressed data was cut short.
+    pub(crate) fn finish(&mut self) -> io::Result<()> {
+        self.finished = true;
+        if !self.received_any {
+            // Empty bodies, e.g. in responses to HEAD requests, don't
+            // contain the compression format's headers either.
+            return Ok(());
+        }
+        match &mut self.coding {
+            Coding::Gzip(decoder) => decoder.try_finish()?,
+            Coding::Deflate { ended: false, .. } => {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "deflate stream ended early",
+                ));
+            }
+            Coding::Deflate { .. } => {}
+        }
+        self.take_output();
+        Ok(())
+    }
+
+    fn take_output(&mut self) {
+        let decoded = match &mut self.coding {
+            Coding::Gzip(decoder) => decoder.get_mut(),
+            Coding::Deflate { decoded, .. } => decoded,
+        };
+        // Swap the buffers, so the old one gets reused for the next
+        // decoded bytes.
+        self.output.clear();
+        self.index = 0;
+        mem::swap(&mut self.output, decoded);
+    }
+}
@@ -2,7 +2,7 @@ use std::{error, fmt, io, str};
 
 /// Represents an error while sending, receiving, or parsing an HTTP response.
 #[derive(Debug)]
-// TODO: Make non-exhaustive for 3.0?
+#[non_exhaustive]
 pub enum Error {
     #[cfg(feature = "json-using-serde")]
     /// Ran into a Serde error.
@@ -22,6 +22,9 @@ pub enum Error {
     /// The chunk did not end after reading the previously read amount
     /// of bytes.
     MalformedChunkEnd,
+    /// The connection was closed before the status line and headers
+    /// of the response had been fully received.
+    ConnectionClosedDuringHeaders,
     /// Couldn't parse the `Content-Length` header's value as an
     /// `usize`.
     MalformedContentLength,
@@ -31,9 +34,71 @@ pub enum Error {
     /// The response's status line length surpasses
     /// [Request::with_max_status_line_size](crate::request::Request::with_max_status_line_length).
     StatusLineOverflow,
+    /// The response's body size surpasses
+    /// [Request::with_max_response_size](crate::request::Request::with_max_response_size).
+    BodyOverflow,
+    /// The response's body is longer than the limit given to
+    /// [ResponseLazy::read_to_end_capped](crate::ResponseLazy::read_to_end_capped).
+    ResponseTooLarge,
+    /// The response's status line could not be parsed, e.g. it did
+    /// not contain a numeric status code.
+    MalformedStatusLine,
+    /// A header in the response was malformed or contained invalid
+    /// characters. Only returned when
+    /// [`with_strict_parsing`](struct.Request.html#method.with_strict_parsing)
+    /// is enabled.
+    MalformedHeader,
+    /// The URL does not have a host, e.g. `http:///path`.
+    EmptyHost,
+    /// The URL's host is an IPv6 address whose closing `]` is
+    /// missing or is followed by something other than a port, path,
+    /// query or fragment, e.g. `http://[::1/path`.
+    InvalidIpv6Host,
+    /// The URL passed to
+    /// [`with_referer`](struct.Request.html#method.with_referer) does
+    /// not start with `http://` or `https://`, or has no host.
+    InvalidReferer,
+    /// The method passed to [`request`](fn.request.html) or parsed
+    /// into a [`Method`](enum.Method.html) is empty or contains
+    /// characters not allowed in a method name.
+    InvalidMethod,
+    /// The response has no `Content-Length` and isn't chunked, but
+    /// the server did not indicate that it would close the connection
+    /// after the body, so the end of the body can't be determined.
+    /// Only returned when
+    /// [`with_strict_parsing`](struct.Request.html#method.with_strict_parsing)
+    /// is enabled.
+    AmbiguousBodyLength,
+    /// The response has both a `Content-Length` and a chunked
+    /// `Transfer-Encoding`, or several `Content-Length` headers with
+    /// different values. Only returned when
+    /// [`with_strict_parsing`](struct.Request.html#method.with_strict_parsing)
+    /// is enabled.
+    ConflictingBodyLength,
+    /// The response to a `HEAD` request contained a body. Only
+    /// returned when
+    /// [`with_strict_parsing`](struct.Request.html#method.with_strict_parsing)
+    /// is enabled.
+    UnexpectedBody,
     /// [ToSocketAddrs](std::net::ToSocketAddrs) did not resolve to an
     /// address.
     AddressNotFound,
+    /// Looking up the addresses of the host failed, with the
+    /// system's resolver or the one set with
+    /// [Request::with_resolver](crate::Request::with_resolver).
+    DnsFailure(io::Error),
+    /// The server (or proxy) refused the connection, e.g. because
+    /// nothing is listening on the port.
+    ConnectionRefused,
+    /// The timeout set with
+    /// [Request::with_timeout](crate::Request::with_timeout) or
+    /// [Request::with_headers_timeout](crate::Request::with_headers_timeout)
+    /// was reached while connecting, sending the request, or waiting
+    /// for the headers of the response. Timeouts while reading the
+    /// body are reported as an [IoError](Error::IoError) of the
+    /// [TimedOut](io::ErrorKind::TimedOut) kind instead, like other
+    /// errors of the [Read](io::Read) implementations.
+    Timeout,
     /// The response was a redirection, but the `Location` header is
     /// missing.
     RedirectLocationMissing,
@@ -43,6 +108,20 @@ pub enum Error {
     /// [`max_redirections`](struct.Request.html#method.with_max_redirections)
     /// redirections, won't follow any more.
     TooManyRedirections,
+    /// The server responded with a client or server error status code
+    /// (400-599). Returned by
+    /// [Response::error_for_status](crate::Response::error_for_status)
+    /// and
+    /// [Response::error_for_status_ref](crate::Response::error_for_status_ref).
+    StatusCode {
+        /// The status code of the response.
+        code: i32,
+        /// The URL of the response.
+        url: String,
+    },
+    /// A header value given to minreq was not valid UTF-8. Contains
+    /// the name of the header.
+    InvalidUtf8InHeader(String),
     /// The response contained invalid UTF-8 where it should be valid
     /// (eg. headers), so the response cannot interpreted correctly.
     InvalidUtf8InResponse,
@@ -67,6 +146,9 @@ pub enum Error {
     ProxyConnect,
     /// The provided credentials were rejected by the proxy server.
     InvalidProxyCreds,
+    /// [`Request::connect_tunnel`](crate::Request::connect_tunnel)
+    /// was called without a proxy to tunnel through.
+    NoProxy,
     // TODO: Uncomment these two for 3.0
     // /// The URL does not start with http:// or https://.
     // InvalidProtocol,
@@ -94,13 +176,30 @@ impl fmt::Display for Error {
             RustlsCreateConnection(err) => write!(f, "error creating rustls connection: {}", err),
             MalformedChunkLength => write!(f, "non-usize chunk length with transfer-encoding: chunked"),
             MalformedChunkEnd => write!(f, "chunk did not end after reading the expected amount of bytes"),
+            ConnectionClosedDuringHeaders => write!(f, "the connection was closed before the response headers were received"),
             MalformedContentLength => write!(f, "non-usize content length"),
             HeadersOverflow => write!(f, "the headers' total size surpassed max_headers_size"),
             StatusLineOverflow => write!(f, "the status line length surpassed max_status_line_length"),
+            BodyOverflow => write!(f, "the body's size surpassed max_response_size"),
+            ResponseTooLarge => write!(f, "the body's size surpassed the limit given to read_to_end_capped"),
+            MalformedStatusLine => write!(f, "the status line could not be parsed"),
+            MalformedHeader => write!(f, "a header was malformed or contained invalid characters"),
+            EmptyHost => write!(f, "the url does not contain a host"),
+            InvalidIpv6Host => write!(f, "the url's ipv6 host is not properly enclosed in brackets"),
+            InvalidReferer => write!(f, "the referer is not an http or https url with a host"),
+            InvalidMethod => write!(f, "the method is empty or contains invalid characters"),
+            AmbiguousBodyLength => write!(f, "the response body has no length, but the connection is kept alive"),
+            ConflictingBodyLength => write!(f, "the response body length was specified in conflicting ways"),
+            UnexpectedBody => write!(f, "the response to a HEAD request contained a body"),
             AddressNotFound => write!(f, "could not resolve host to a socket address"),
+            DnsFailure(err) => write!(f, "could not resolve host: {}", err),
+            ConnectionRefused => write!(f, "the connection was refused"),
+            Timeout => write!(f, "the timeout of the request was reached"),
             RedirectLocationMissing => write!(f, "redirection location header missing"),
             InfiniteRedirectionLoop => write!(f, "infinite redirection loop detected"),
             TooManyRedirections => write!(f, "too many redirections (over the max)"),
+            StatusCode { code, url } => write!(f, "the server responded with status code {} for {}", code, url),
+            InvalidUtf8InHeader(name) => write!(f, "the value of the header {} is not valid utf-8", name),
             InvalidUtf8InResponse => write!(f, "response contained invalid utf-8 where valid utf-8 was expected"),
             HttpsFeatureNotEnabled => write!(f, "request url contains https:// but the https feature is not enabled"),
             PunycodeFeatureNotEnabled => write!(f, "non-ascii urls needs to be converted into punycode, and the feature is missing"),
@@ -109,6 +208,7 @@ impl fmt::Display for Error {
             BadProxyCreds => write!(f, "the provided proxy credentials are malformed"),
             ProxyConnect => write!(f, "could not connect to the proxy server"),
             InvalidProxyCreds => write!(f, "the provided proxy credentials are invalid"),
+            NoProxy => write!(f, "no proxy was set to open the tunnel through"),
             // TODO: Uncomment these two for 3.0
             // InvalidProtocol => write!(f, "the url does not start with http:// or https://"),
             // InvalidProtocolInRedirect => write!(f, "got redirected to an absolute url which does not start with http:// or https://"),
@@ -124,6 +224,7 @@ impl error::Error for Error {
             #[cfg(feature = "json-using-serde")]
             SerdeJsonError(err) => Some(err),
             IoError(err) => Some(err),
+            DnsFailure(err) => Some(err),
             InvalidUtf8InBody(err) => Some(err),
             #[cfg(feature = "rustls")]
             RustlsCreateConnection(err) => Some(err),
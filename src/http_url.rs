@@ -1,8 +1,9 @@
+use std::borrow::Cow;
 use std::fmt::{self, Write};
 
 use crate::Error;
 
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub(crate) enum Port {
     ImplicitHttp,
     ImplicitHttps,
@@ -27,11 +28,12 @@ impl Port {
 /// ```text
 /// scheme "://" host [ ":" port ] path [ "?" query ] [ "#" fragment ]
 /// ```
-#[derive(Clone, PartialEq)]
+#[derive(Clone, PartialEq, Eq, Hash)]
 pub(crate) struct HttpUrl {
     /// If scheme is "https", true, if "http", false.
     pub(crate) https: bool,
-    /// `host`
+    /// `host`, without the brackets around IPv6 addresses, so that it
+    /// can be resolved as-is. See [`HttpUrl::bracketed_host`].
     pub(crate) host: String,
     /// `[":" port]`
     pub(crate) port: Port,
@@ -45,6 +47,8 @@ impl HttpUrl {
     pub(crate) fn parse(url: &str, redirected_from: Option<&HttpUrl>) -> Result<HttpUrl, Error> {
         enum UrlParseStatus {
             Host,
+            Ipv6Host,
+            Ipv6HostEnd,
             Port,
             PathAndQuery,
             Fragment,
@@ -78,14 +82,43 @@ impl HttpUrl {
                             resource.push(c);
                         }
                         ':' => status = UrlParseStatus::Port,
+                        '#' => {
+                            status = UrlParseStatus::Fragment;
+                            path_and_query = Some(String::new());
+                        }
+                        // An IPv6 address, e.g. [::1], which contains
+                        // colons that aren't followed by the port.
+                        '[' if host.is_empty() => status = UrlParseStatus::Ipv6Host,
                         _ => host.push(c),
                     }
                 }
+                UrlParseStatus::Ipv6Host => match c {
+                    ']' => status = UrlParseStatus::Ipv6HostEnd,
+                    _ => host.push(c),
+                },
+                // Only a port, path, query or fragment can follow the
+                // closing bracket.
+                UrlParseStatus::Ipv6HostEnd => match c {
+                    ':' => status = UrlParseStatus::Port,
+                    '/' | '?' => {
+                        status = UrlParseStatus::PathAndQuery;
+                        resource.push(c);
+                    }
+                    '#' => {
+                        status = UrlParseStatus::Fragment;
+                        path_and_query = Some(String::new());
+                    }
+                    _ => return Err(Error::InvalidIpv6Host),
+                },
                 UrlParseStatus::Port => match c {
                     '/' | '?' => {
                         status = UrlParseStatus::PathAndQuery;
                         resource.push(c);
                     }
+                    '#' => {
+                        status = UrlParseStatus::Fragment;
+                        path_and_query = Some(String::new());
+                    }
                     _ => port.push(c),
                 },
                 UrlParseStatus::PathAndQuery if c == '#' => {
@@ -135,6 +168,13 @@ impl HttpUrl {
                 },
             }
         }
+        if let UrlParseStatus::Ipv6Host = status {
+            return Err(Error::InvalidIpv6Host);
+        }
+        if host.is_empty() {
+            return Err(Error::EmptyHost);
+        }
+
         let (mut path_and_query, mut fragment) = if let Some(path_and_query) = path_and_query {
             (path_and_query, Some(resource))
         } else {
@@ -150,9 +190,11 @@ impl HttpUrl {
             }
         }
 
-        // Ensure the resource is *something*
-        if path_and_query.is_empty() {
-            path_and_query.push('/');
+        // Ensure the resource is *something*, and that it's a valid
+        // origin-form request target, even for URLs with an empty path
+        // and a query, like "http://example.com?a=b"
+        if !path_and_query.starts_with('/') {
+            path_and_query.insert(0, '/');
         }
 
         // Set appropriate port
@@ -173,13 +215,27 @@ impl HttpUrl {
         })
     }
 
+    /// Returns the host as written in URLs and the `Host` header, i.e.
+    /// with IPv6 addresses in brackets, as specified in [RFC 3986
+    /// section 3.2.2](https://datatracker.ietf.org/doc/html/rfc3986#section-3.2.2).
+    pub(crate) fn bracketed_host(&self) -> Cow<'_, str> {
+        if self.host.contains(':') {
+            Cow::Owned(format!("[{}]", self.host))
+        } else {
+            Cow::Borrowed(&self.host)
+        }
+    }
+
     /// Writes the `scheme "://" host [ ":" port ]` part to the destination.
+    ///
+    /// Only fails if writing into `dst` fails, which a `String` never
+    /// does.
     pub(crate) fn write_base_url_to<W: Write>(&self, dst: &mut W) -> fmt::Result {
         write!(
             dst,
             "http{s}://{host}",
             s = if self.https { "s" } else { "" },
-            host = &self.host,
+            host = self.bracketed_host(),
         )?;
         if let Port::Explicit(port) = self.port {
             write!(dst, ":{}", port)?;
@@ -187,7 +243,9 @@ impl HttpUrl {
         Ok(())
     }
 
-    /// Writes the `path [ "?" query ] [ "#" fragment ]` part to the destination.
+    /// Writes the `path [ "?" query ] [ "#" fragment ]` part to the
+    /// destination. Like `write_base_url_to`, this only fails if
+    /// writing into `dst` fails.
     pub(crate) fn write_resource_to<W: Write>(&self, dst: &mut W) -> fmt::Result {
         write!(
             dst,
@@ -94,6 +94,40 @@
 //!
 //! This feature enables HTTP proxy support. See [Proxy].
 //!
+//! ## `http-interop`
+//!
+//! This feature enables conversions from the types of the
+//! [`http`](https://crates.io/crates/http) crate, e.g.
+//! [`Request::with_header_map`].
+//!
+//! ## `encoding`
+//!
+//! This feature enables decoding response bodies in other charsets
+//! than UTF-8, e.g. ISO-8859-1 or Shift_JIS, using the
+//! [`encoding_rs`](https://crates.io/crates/encoding_rs) crate. See
+//! [`Response::text`].
+//!
+//! ## `decompress`
+//!
+//! This feature enables decompressing response bodies compressed with
+//! gzip or deflate, using the
+//! [`flate2`](https://crates.io/crates/flate2) crate. Requests are
+//! sent with an `Accept-Encoding: gzip, deflate` header, unless one
+//! has been set already, and bodies with a matching
+//! `Content-Encoding` are decompressed as they're read. The
+//! `Content-Encoding` and `Content-Length` headers of the response
+//! are left as they were received, so `Content-Length` is the
+//! compressed length. Use
+//! [`Request::without_auto_decompression`](struct.Request.html#method.without_auto_decompression)
+//! to get the compressed body of a single request instead.
+//!
+//! ## `mmap`
+//!
+//! This feature enables
+//! [`ResponseLazy::save_to_mmap`](struct.ResponseLazy.html#method.save_to_mmap),
+//! which downloads a body into a memory-mapped file, using the
+//! [`memmap2`](https://crates.io/crates/memmap2) crate.
+//!
 //! ## `urlencoding`
 //!
 //! This feature enables percent-encoding for the URL resource when
@@ -185,8 +219,9 @@
 //! `.with_proxy()` on your request.
 //!
 //! Supported proxy formats are `host:port` and
-//! `user:password@proxy:host`. Only HTTP CONNECT proxies are
-//! supported at this time.
+//! `user:password@proxy:host`, optionally prefixed with `http://`,
+//! `socks5://`, or `socks5h://` for SOCKS5 proxies which resolve the
+//! host themselves. See [`Proxy::new`](struct.Proxy.html#method.new).
 //!
 //! ```no_run
 //! # fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -224,6 +259,21 @@
 //!   ```
 //! If the timeout is set with `with_timeout`, the environment
 //! variable will be ignored.
+//!
+//! # Default headers
+//!
+//! Headers can be added to every request made by the program with the
+//! environment variable `MINREQ_DEFAULT_HEADERS`, containing one
+//! `Name: Value` header per line. This is useful for e.g. adding a
+//! tracing header to all requests:
+//! ```text,ignore
+//! $ MINREQ_DEFAULT_HEADERS="X-Trace-Id: 1234" ./foo
+//! ```
+//! Headers set on the request itself take precedence over the default
+//! headers of the same name.
+//!
+//! Unless set otherwise, requests are sent with a `User-Agent` of
+//! `minreq/<version>`, see [`version`].
 
 #![deny(missing_docs)]
 
@@ -243,12 +293,19 @@ extern crate webpki;
 #[cfg(feature = "webpki-roots")]
 extern crate webpki_roots;
 
+#[cfg(feature = "http-interop")]
+extern crate http;
 #[cfg(feature = "json-using-serde")]
 extern crate serde;
 #[cfg(feature = "json-using-serde")]
 extern crate serde_json;
 
+mod auth;
 mod connection;
+mod cookie;
+mod date;
+#[cfg(feature = "decompress")]
+mod decompress;
 mod error;
 mod http_url;
 #[cfg(feature = "proxy")]
@@ -256,8 +313,40 @@ mod proxy;
 mod request;
 mod response;
 
+pub use auth::AuthChallenge;
+pub use connection::HttpStream;
+pub use cookie::Cookie;
 pub use error::*;
 #[cfg(feature = "proxy")]
 pub use proxy::*;
 pub use request::*;
 pub use response::*;
+
+/// Returns the version of minreq, e.g. "2.13.0". This is also sent
+/// in the default `User-Agent` header, as `minreq/<version>`.
+pub fn version() -> &'static str {
+    env!("CARGO_PKG_VERSION")
+}
+
+/// Returns true if minreq was compiled with one of the `https`
+/// features, i.e. if HTTPS requests can be made. Otherwise, they
+/// fail with
+/// [`HttpsFeatureNotEnabled`](enum.Error.html#variant.HttpsFeatureNotEnabled).
+pub fn https_available() -> bool {
+    tls_backend().is_some()
+}
+
+/// Returns the name of the TLS implementation minreq was compiled
+/// with: "rustls", "openssl", or "native-tls". `None` if none of the
+/// `https` features are enabled.
+pub fn tls_backend() -> Option<&'static str> {
+    if cfg!(feature = "rustls") {
+        Some("rustls")
+    } else if cfg!(feature = "openssl") {
+        Some("openssl")
+    } else if cfg!(feature = "native-tls") {
+        Some("native-tls")
+    } else {
+        None
+    }
+}
@@ -1,5 +1,20 @@
+use crate::auth::base64_encode;
 use crate::error::Error;
 use crate::ParsedRequest;
+use std::io::{Read, Write};
+use std::net::IpAddr;
+
+// Constants of the SOCKS5 protocol, see RFC 1928 and RFC 1929 (for
+// the username/password authentication).
+const SOCKS5_VERSION: u8 = 5;
+const SOCKS5_NO_AUTHENTICATION: u8 = 0;
+const SOCKS5_USERNAME_PASSWORD: u8 = 2;
+const SOCKS5_NO_ACCEPTABLE_METHODS: u8 = 0xFF;
+const SOCKS5_USERNAME_PASSWORD_VERSION: u8 = 1;
+const SOCKS5_CONNECT: u8 = 1;
+const SOCKS5_IPV4: u8 = 1;
+const SOCKS5_DOMAIN_NAME: u8 = 3;
+const SOCKS5_IPV6: u8 = 4;
 
 /// Kind of proxy connection (Basic, Digest, etc)
 #[derive(Clone, PartialEq, Eq, Debug)]
@@ -7,11 +22,28 @@ pub(crate) enum ProxyKind {
     Basic,
 }
 
-/// Proxy configuration. Only HTTP CONNECT proxies are supported (no SOCKS or
+/// The protocol spoken with the proxy.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum ProxyProtocol {
+    Http,
+    /// SOCKS5, with the host of the request resolved locally
+    /// (`socks5://`), or by the proxy (`socks5h://`).
+    Socks5 {
+        remote_dns: bool,
+    },
+}
+
+/// Proxy configuration. HTTP and SOCKS5 proxies are supported (no
 /// HTTPS).
 ///
-/// When credentials are provided, the Basic authentication type is used for
-/// Proxy-Authorization.
+/// Through HTTP proxies, HTTPS requests are tunneled with CONNECT,
+/// while plain HTTP requests are sent to the proxy as-is, with the
+/// absolute URL as the request target. Through SOCKS5 proxies, all
+/// requests are tunneled.
+///
+/// When credentials are provided, the Basic authentication type is
+/// used for Proxy-Authorization with HTTP proxies, and the
+/// username/password authentication with SOCKS5 proxies.
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub struct Proxy {
     pub(crate) server: String,
@@ -19,6 +51,7 @@ pub struct Proxy {
     pub(crate) user: Option<String>,
     pub(crate) password: Option<String>,
     pub(crate) kind: ProxyKind,
+    pub(crate) protocol: ProxyProtocol,
 }
 
 impl Proxy {
@@ -45,26 +78,42 @@ impl Proxy {
     ///
     /// ```plaintext
     /// [http://][user[:password]@]host[:port]
+    /// socks5://[user[:password]@]host[:port]
+    /// socks5h://[user[:password]@]host[:port]
     /// ```
     ///
-    /// The default port is 8080, to be changed to 1080 in minreq 3.0.
+    /// With `socks5://`, the host of the request is resolved locally,
+    /// and with `socks5h://`, by the proxy.
+    ///
+    /// The default port is 1080 for SOCKS5 proxies. For HTTP proxies
+    /// it's 8080, to be changed to 1080 in minreq 3.0.
     ///
     /// # Example
     ///
     /// ```
     /// let proxy = minreq::Proxy::new("user:password@localhost:1080").unwrap();
     /// let request = minreq::post("http://example.com").with_proxy(proxy);
+    ///
+    /// let proxy = minreq::Proxy::new("socks5h://localhost").unwrap();
+    /// let request = minreq::post("http://example.com").with_proxy(proxy);
     /// ```
     ///
     pub fn new<S: AsRef<str>>(proxy: S) -> Result<Self, Error> {
         let proxy = proxy.as_ref();
-        let authority = if let Some((proto, auth)) = split_once(proxy, "://") {
-            if proto != "http" {
-                return Err(Error::BadProxy);
-            }
-            auth
+        let (protocol, authority) = if let Some((proto, auth)) = split_once(proxy, "://") {
+            let protocol = match proto {
+                "http" => ProxyProtocol::Http,
+                "socks5" => ProxyProtocol::Socks5 { remote_dns: false },
+                "socks5h" => ProxyProtocol::Socks5 { remote_dns: true },
+                _ => return Err(Error::BadProxy),
+            };
+            (protocol, auth)
         } else {
-            proxy
+            (ProxyProtocol::Http, proxy)
+        };
+        let default_port = match protocol {
+            ProxyProtocol::Http => 8080,
+            ProxyProtocol::Socks5 { .. } => 1080,
         };
 
         let ((user, password), host) = if let Some((userinfo, host)) = rsplit_once(authority, "@") {
@@ -79,47 +128,170 @@ impl Proxy {
             server: host,
             user,
             password,
-            port: port.unwrap_or(8080),
+            port: port.unwrap_or(default_port),
             kind: ProxyKind::Basic,
+            protocol,
         })
     }
 
-    pub(crate) fn connect(&self, proxied_req: &ParsedRequest) -> String {
-        let authorization = if let Some(user) = &self.user {
+    /// Returns true if this is a SOCKS5 proxy.
+    pub(crate) fn is_socks5(&self) -> bool {
+        matches!(self.protocol, ProxyProtocol::Socks5 { .. })
+    }
+
+    /// Returns the `Proxy-Authorization` header line to send to the
+    /// proxy, or an empty string if no credentials were provided.
+    pub(crate) fn authorization_header(&self) -> String {
+        if let Some(user) = &self.user {
             match self.kind {
                 ProxyKind::Basic => {
                     let creds = if let Some(password) = &self.password {
-                        base64::encode(format!("{}:{}", user, password))
+                        base64_encode(format!("{}:{}", user, password).as_bytes())
                     } else {
-                        base64::encode(user)
+                        base64_encode(user.as_bytes())
                     };
                     format!("Proxy-Authorization: Basic {}\r\n", creds)
                 }
             }
         } else {
             String::new()
-        };
-        let host = &proxied_req.url.host;
+        }
+    }
+
+    pub(crate) fn connect(&self, proxied_req: &ParsedRequest) -> String {
+        let authorization = self.authorization_header();
+        let host = proxied_req.url.bracketed_host();
         let port = proxied_req.url.port.port();
         format!(
-            "CONNECT {}:{} HTTP/1.1\r\n{}\r\n",
-            host, port, authorization
+            "CONNECT {host}:{port} HTTP/1.1\r\nHost: {host}:{port}\r\nProxy-Connection: keep-alive\r\n{authorization}\r\n",
+            host = host,
+            port = port,
+            authorization = authorization,
         )
     }
 
-    pub(crate) fn verify_response(response: &[u8]) -> Result<(), Error> {
+    /// Asks the SOCKS5 proxy at the other end of `stream` to connect
+    /// to `host`:`port`, authenticating first if credentials were
+    /// provided. `address` is sent instead of `host` if given, for
+    /// when the host has been resolved locally.
+    pub(crate) fn socks5_handshake<S: Read + Write>(
+        &self,
+        stream: &mut S,
+        host: &str,
+        port: u16,
+        address: Option<IpAddr>,
+    ) -> Result<(), Error> {
+        stream.write_all(&self.socks5_greeting())?;
+        let mut reply = [0; 2];
+        stream.read_exact(&mut reply)?;
+        match reply {
+            [SOCKS5_VERSION, SOCKS5_NO_AUTHENTICATION] => {}
+            [SOCKS5_VERSION, SOCKS5_USERNAME_PASSWORD] if self.user.is_some() => {
+                stream.write_all(&self.socks5_authentication()?)?;
+                stream.read_exact(&mut reply)?;
+                if reply != [SOCKS5_USERNAME_PASSWORD_VERSION, 0] {
+                    return Err(Error::InvalidProxyCreds);
+                }
+            }
+            [SOCKS5_VERSION, SOCKS5_NO_ACCEPTABLE_METHODS] => return Err(Error::InvalidProxyCreds),
+            _ => return Err(Error::BadProxy),
+        }
+
+        stream.write_all(&socks5_connect_request(host, port, address)?)?;
+        let mut reply = [0; 4];
+        stream.read_exact(&mut reply)?;
+        if reply[0] != SOCKS5_VERSION {
+            return Err(Error::BadProxy);
+        }
+        if reply[1] != 0 {
+            return Err(Error::ProxyConnect);
+        }
+        // The address the proxy connected from isn't needed, but it
+        // has to be read out of the way of the tunneled data.
+        let address_length = match reply[3] {
+            SOCKS5_IPV4 => 4,
+            SOCKS5_IPV6 => 16,
+            SOCKS5_DOMAIN_NAME => {
+                let mut length = [0];
+                stream.read_exact(&mut length)?;
+                length[0] as usize
+            }
+            _ => return Err(Error::BadProxy),
+        };
+        let mut bound_address = vec![0; address_length + 2];
+        stream.read_exact(&mut bound_address)?;
+        Ok(())
+    }
+
+    fn socks5_greeting(&self) -> Vec<u8> {
+        if self.user.is_some() {
+            vec![
+                SOCKS5_VERSION,
+                2,
+                SOCKS5_NO_AUTHENTICATION,
+                SOCKS5_USERNAME_PASSWORD,
+            ]
+        } else {
+            vec![SOCKS5_VERSION, 1, SOCKS5_NO_AUTHENTICATION]
+        }
+    }
+
+    fn socks5_authentication(&self) -> Result<Vec<u8>, Error> {
+        let user = self.user.as_deref().unwrap_or("");
+        let password = self.password.as_deref().unwrap_or("");
+        if user.len() > 255 || password.len() > 255 {
+            return Err(Error::BadProxyCreds);
+        }
+        let mut request = vec![SOCKS5_USERNAME_PASSWORD_VERSION, user.len() as u8];
+        request.extend_from_slice(user.as_bytes());
+        request.push(password.len() as u8);
+        request.extend_from_slice(password.as_bytes());
+        Ok(request)
+    }
+
+    /// Returns the status code of the proxy's response to `CONNECT`,
+    /// if it indicates that the tunnel was established.
+    pub(crate) fn verify_response(response: &[u8]) -> Result<i32, Error> {
         let response_string = String::from_utf8_lossy(response);
         let top_line = response_string.lines().next().ok_or(Error::ProxyConnect)?;
         let status_code = top_line.split_whitespace().nth(1).ok_or(Error::BadProxy)?;
 
-        match status_code {
-            "200" => Ok(()),
-            "401" | "407" => Err(Error::InvalidProxyCreds),
+        match status_code.parse::<i32>() {
+            Ok(code @ 200..=299) => Ok(code),
+            Ok(401) | Ok(407) => Err(Error::InvalidProxyCreds),
             _ => Err(Error::BadProxy),
         }
     }
 }
 
+fn socks5_connect_request(
+    host: &str,
+    port: u16,
+    address: Option<IpAddr>,
+) -> Result<Vec<u8>, Error> {
+    let mut request = vec![SOCKS5_VERSION, SOCKS5_CONNECT, 0];
+    match address.or_else(|| host.parse().ok()) {
+        Some(IpAddr::V4(address)) => {
+            request.push(SOCKS5_IPV4);
+            request.extend_from_slice(&address.octets());
+        }
+        Some(IpAddr::V6(address)) => {
+            request.push(SOCKS5_IPV6);
+            request.extend_from_slice(&address.octets());
+        }
+        None => {
+            if host.len() > 255 {
+                return Err(Error::AddressNotFound);
+            }
+            request.push(SOCKS5_DOMAIN_NAME);
+            request.push(host.len() as u8);
+            request.extend_from_slice(host.as_bytes());
+        }
+    }
+    request.extend_from_slice(&port.to_be_bytes());
+    Ok(request)
+}
+
 #[allow(clippy::manual_split_once)]
 /// Replacement for str::split_once until MSRV is at least 1.52.0.
 fn split_once<'a>(string: &'a str, pattern: &str) -> Option<(&'a str, &'a str)> {
@@ -140,7 +312,46 @@ fn rsplit_once<'a>(string: &'a str, pattern: &str) -> Option<(&'a str, &'a str)>
 
 #[cfg(test)]
 mod tests {
-    use super::Proxy;
+    use super::{Proxy, ProxyProtocol};
+    use std::io::{self, Read, Write};
+
+    /// A stream which reads from the canned replies, and records what
+    /// was written into it.
+    struct MockStream {
+        replies: io::Cursor<Vec<u8>>,
+        written: Vec<u8>,
+    }
+
+    impl Read for MockStream {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.replies.read(buf)
+        }
+    }
+
+    impl Write for MockStream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.written.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn handshake(
+        proxy: &str,
+        replies: &[u8],
+        address: Option<&str>,
+    ) -> (Result<(), crate::Error>, Vec<u8>) {
+        let mut stream = MockStream {
+            replies: io::Cursor::new(replies.to_vec()),
+            written: Vec::new(),
+        };
+        let address = address.map(|address| address.parse().unwrap());
+        let proxy = Proxy::new(proxy).unwrap();
+        let result = proxy.socks5_handshake(&mut stream, "example.com", 443, address);
+        (result, stream.written)
+    }
 
     #[test]
     fn parse_proxy() {
@@ -159,4 +370,79 @@ mod tests {
         assert_eq!(proxy.server, String::from("localhost"));
         assert_eq!(proxy.port, 1080);
     }
+
+    #[test]
+    fn parse_socks5_proxy() {
+        let proxy = Proxy::new("socks5://user:pw@localhost").unwrap();
+        assert_eq!(proxy.protocol, ProxyProtocol::Socks5 { remote_dns: false });
+        assert_eq!(proxy.user, Some(String::from("user")));
+        assert_eq!(proxy.server, String::from("localhost"));
+        assert_eq!(proxy.port, 1080);
+
+        let proxy = Proxy::new("socks5h://localhost:9050").unwrap();
+        assert_eq!(proxy.protocol, ProxyProtocol::Socks5 { remote_dns: true });
+        assert_eq!(proxy.port, 9050);
+
+        assert_eq!(
+            Proxy::new("localhost").unwrap().protocol,
+            ProxyProtocol::Http
+        );
+        assert!(Proxy::new("socks4://localhost").is_err());
+    }
+
+    #[test]
+    fn socks5_handshake() {
+        // No authentication, the proxy resolves the name.
+        let replies = [5, 0, 5, 0, 0, 1, 10, 0, 0, 1, 0x1F, 0x90];
+        let (result, written) = handshake("socks5h://proxy", &replies, None);
+        result.unwrap();
+        let mut expected = vec![5, 1, 0, 5, 1, 0, 3, 11];
+        expected.extend_from_slice(b"example.com");
+        expected.extend_from_slice(&[1, 0xBB]);
+        assert_eq!(written, expected);
+
+        // Username/password authentication, with a locally resolved
+        // IPv6 address, and a domain name as the bound address.
+        let replies = [5, 2, 1, 0, 5, 0, 0, 3, 1, b'a', 0, 0];
+        let (result, written) = handshake("socks5://user:pw@proxy", &replies, Some("::1"));
+        result.unwrap();
+        let mut expected = vec![5, 2, 0, 2, 1, 4, b'u', b's', b'e', b'r', 2, b'p', b'w'];
+        expected.extend_from_slice(&[5, 1, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+        expected.extend_from_slice(&[1, 0xBB]);
+        assert_eq!(written, expected);
+    }
+
+    #[test]
+    fn socks5_handshake_errors() {
+        let error = |proxy, replies: &[u8]| handshake(proxy, replies, None).0.unwrap_err();
+        let rejected_creds = error("socks5://user:pw@proxy", &[5, 2, 1, 1]);
+        assert!(matches!(rejected_creds, crate::Error::InvalidProxyCreds));
+        let no_methods = error("socks5://proxy", &[5, 0xFF]);
+        assert!(matches!(no_methods, crate::Error::InvalidProxyCreds));
+        let refused = error("socks5://proxy", &[5, 0, 5, 5, 0, 1]);
+        assert!(matches!(refused, crate::Error::ProxyConnect));
+        let not_socks5 = error("socks5://proxy", b"HTTP/1.1 400 Bad Request\r\n");
+        assert!(matches!(not_socks5, crate::Error::BadProxy));
+        let cut_short = error("socks5://proxy", &[5, 0, 5, 0, 0, 1, 127]);
+        assert!(matches!(cut_short, crate::Error::IoError(_)));
+    }
+
+    #[test]
+    fn verify_connect_response() {
+        let verify = |response: &str| Proxy::verify_response(response.as_bytes());
+        assert_eq!(
+            verify("HTTP/1.1 200 Connection established\r\n\r\n").unwrap(),
+            200
+        );
+        assert_eq!(
+            verify("HTTP/1.0 200 Tunnel ok, go ahead\r\n\r\n").unwrap(),
+            200
+        );
+        assert_eq!(verify("HTTP/1.1 201 Created\r\n\r\n").unwrap(), 201);
+        assert!(verify("HTTP/1.1 407 Proxy Authentication Required\r\n\r\n").is_err());
+        assert!(verify("HTTP/1.1 302 Found\r\n\r\n").is_err());
+        assert!(verify("HTTP/1.1 2xx Weird\r\n\r\n").is_err());
+        assert!(verify("HTTP/1.1 502 Bad Gateway\r\n\r\n").is_err());
+        assert!(verify("").is_err());
+    }
 }
@@ -1,7 +1,16 @@
+use crate::auth::{parse_challenges, AuthChallenge};
+use crate::cookie::{parse_set_cookie, Cookie};
+#[cfg(feature = "decompress")]
+use crate::decompress::Decoder;
+use crate::request::Extensions;
 use crate::{connection::HttpStream, Error};
+use std::any::Any;
 use std::collections::HashMap;
-use std::io::{self, BufReader, Bytes, Read};
+use std::io::{self, BufRead, BufReader, Read};
+#[cfg(feature = "mmap")]
+use std::path::Path;
 use std::str;
+use std::time::Duration;
 
 const BACKING_READ_BUFFER_LENGTH: usize = 16 * 1024;
 const MAX_CONTENT_LENGTH: usize = 16 * 1024;
@@ -32,14 +41,31 @@ pub struct Response {
     /// <http://example.com?foo=bar> would be corrected to
     /// <http://example.com/?foo=bar>).
     pub url: String,
+    /// The amount of redirections that were followed to get this
+    /// response.
+    pub redirect_count: usize,
 
+    raw_headers: Vec<String>,
+    tls_info: Option<TlsInfo>,
+    timings: Timings,
+    proxy_connect_status: Option<i32>,
+    proxy_source: Option<&'static str>,
+    used_ipv6: bool,
+    extensions: Extensions,
     body: Vec<u8>,
 }
 
 impl Response {
-    pub(crate) fn create(mut parent: ResponseLazy, is_head: bool) -> Result<Response, Error> {
+    pub(crate) fn create(
+        mut parent: ResponseLazy,
+        is_head: bool,
+        max_response_size: Option<usize>,
+        body_capacity_hint: usize,
+    ) -> Result<Response, Error> {
         let mut body = Vec::new();
         if !is_head && parent.status_code != 204 && parent.status_code != 304 {
+            let capacity_cap = max_response_size.unwrap_or(usize::MAX);
+            body.reserve(body_capacity_hint.min(capacity_cap));
             for byte in &mut parent {
                 let (byte, length) = byte?;
                 body.reserve(length);
@@ -52,6 +78,14 @@ impl Response {
             reason_phrase,
             headers,
             url,
+            redirect_count,
+            raw_headers,
+            tls_info,
+            timings,
+            proxy_connect_status,
+            proxy_source,
+            used_ipv6,
+            extensions,
             ..
         } = parent;
 
@@ -60,10 +94,88 @@ impl Response {
             reason_phrase,
             headers,
             url,
+            redirect_count,
+            raw_headers,
+            tls_info,
+            timings,
+            proxy_connect_status,
+            proxy_source,
+            used_ipv6,
+            extensions,
             body,
         })
     }
 
+    /// Creates a response from the given parts, without sending a
+    /// request. This is meant for testing code that handles
+    /// responses, without needing a server to respond to it.
+    ///
+    /// The header names are lowercased, as they would be in a
+    /// received response. The response has no TLS information, no
+    /// timings, and hasn't been redirected.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    ///
+    /// fn greeting(response: &minreq::Response) -> Option<&str> {
+    ///     if response.status_code == 200 {
+    ///         response.as_str().ok()
+    ///     } else {
+    ///         None
+    ///     }
+    /// }
+    ///
+    /// let mut headers = HashMap::new();
+    /// headers.insert("Content-Type".to_string(), "text/plain".to_string());
+    /// let response = minreq::Response::from_parts(
+    ///     200,
+    ///     "OK",
+    ///     headers,
+    ///     "http://example.com/",
+    ///     "Hello, World!",
+    /// );
+    /// assert_eq!(response.headers["content-type"], "text/plain");
+    /// assert_eq!(greeting(&response), Some("Hello, World!"));
+    /// ```
+    pub fn from_parts<R, U, B>(
+        status_code: i32,
+        reason_phrase: R,
+        headers: HashMap<String, String>,
+        url: U,
+        body: B,
+    ) -> Response
+    where
+        R: Into<String>,
+        U: Into<String>,
+        B: Into<Vec<u8>>,
+    {
+        let headers = headers
+            .into_iter()
+            .map(|(name, value)| (name.to_lowercase(), value))
+            .collect::<HashMap<String, String>>();
+        let raw_headers = headers
+            .iter()
+            .map(|(name, value)| format!("{}: {}", name, value))
+            .collect();
+        Response {
+            status_code,
+            reason_phrase: reason_phrase.into(),
+            headers,
+            url: url.into(),
+            redirect_count: 0,
+            raw_headers,
+            tls_info: None,
+            timings: Timings::default(),
+            proxy_connect_status: None,
+            proxy_source: None,
+            used_ipv6: false,
+            extensions: Extensions::default(),
+            body: body.into(),
+        }
+    }
+
     /// Returns the body as an `&str`.
     ///
     /// # Errors
@@ -128,6 +240,293 @@ impl Response {
         self.body
     }
 
+    /// Returns the response if its status code is not a client or
+    /// server error, i.e. it's not in the range 400-599.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StatusCode`](enum.Error.html#variant.StatusCode) if
+    /// the status code is in the range 400-599.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> Result<(), minreq::Error> {
+    /// let response = minreq::get("http://example.com").send()?.error_for_status()?;
+    /// println!("{}", response.as_str()?);
+    /// # Ok(()) }
+    /// ```
+    pub fn error_for_status(self) -> Result<Response, Error> {
+        self.error_for_status_ref()?;
+        Ok(self)
+    }
+
+    /// Like [`error_for_status`](#method.error_for_status), but
+    /// borrows the response, so that e.g. the body of the error page
+    /// can still be read after getting the error.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`StatusCode`](enum.Error.html#variant.StatusCode) if
+    /// the status code is in the range 400-599.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> Result<(), minreq::Error> {
+    /// let response = minreq::get("http://example.com").send()?;
+    /// if let Err(err) = response.error_for_status_ref() {
+    ///     eprintln!("{}: {}", err, response.as_str()?);
+    /// }
+    /// # Ok(()) }
+    /// ```
+    pub fn error_for_status_ref(&self) -> Result<&Response, Error> {
+        if (400..600).contains(&self.status_code) {
+            Err(Error::StatusCode {
+                code: self.status_code,
+                url: self.url.clone(),
+            })
+        } else {
+            Ok(self)
+        }
+    }
+
+    /// Returns the amount of headers in the response.
+    pub fn header_count(&self) -> usize {
+        self.headers.len()
+    }
+
+    /// Returns an iterator over the names of the headers in the
+    /// response. The names are all lowercase.
+    pub fn header_names(&self) -> impl Iterator<Item = &str> {
+        self.headers.keys().map(String::as_str)
+    }
+
+    /// Returns an iterator over every value received for the header
+    /// with the given name, in the order they were received. The name
+    /// is case-insensitive.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> Result<(), minreq::Error> {
+    /// let response = minreq::get("http://example.com").send()?;
+    /// for value in response.header_values("Content-Type") {
+    ///     println!("content-type: {}", value);
+    /// }
+    /// # Ok(()) }
+    /// ```
+    pub fn header_values<'a>(&'a self, name: &str) -> impl Iterator<Item = &'a str> + 'a {
+        let name = name.to_string();
+        self.raw_headers
+            .iter()
+            .filter_map(|line| split_header(line))
+            .filter(move |(header_name, _)| header_name.eq_ignore_ascii_case(&name))
+            .map(|(_, value)| value.strip_prefix(' ').unwrap_or(value))
+    }
+
+    /// Returns every value received for the header with the given
+    /// name, in the order they were received. The name is
+    /// case-insensitive.
+    ///
+    /// Unlike [`headers`](#structfield.headers), which only keeps the
+    /// last value of a header that was received multiple times, this
+    /// returns all of them, e.g. every `Set-Cookie`. This is
+    /// [`header_values`](#method.header_values), collected.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> Result<(), minreq::Error> {
+    /// let response = minreq::get("http://example.com").send()?;
+    /// for cookie in response.headers_all("Set-Cookie") {
+    ///     println!("set-cookie: {}", cookie);
+    /// }
+    /// # Ok(()) }
+    /// ```
+    pub fn headers_all(&self, name: &str) -> Vec<&str> {
+        self.header_values(name).collect()
+    }
+
+    /// Parses the value of the header with the given name into `T`,
+    /// e.g. a number. The name is case-insensitive, and the value is
+    /// trimmed before parsing. Returns `None` if the header isn't in
+    /// the response.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> Result<(), minreq::Error> {
+    /// let response = minreq::get("http://example.com").send()?;
+    /// if let Some(Ok(remaining)) = response.header_parsed::<u64>("X-RateLimit-Remaining") {
+    ///     println!("{} requests left", remaining);
+    /// }
+    /// # Ok(()) }
+    /// ```
+    pub fn header_parsed<T: str::FromStr>(&self, name: &str) -> Option<Result<T, T::Err>> {
+        self.headers
+            .get(&name.to_lowercase())
+            .map(|value| value.trim().parse())
+    }
+
+    /// Returns the value of the header with the given name exactly as
+    /// it was received, unlike [`headers`](#structfield.headers),
+    /// where the space after the `:` is removed. The name is
+    /// case-insensitive. If the header was received multiple times,
+    /// the last value is returned, like in `headers`.
+    ///
+    /// This is useful when byte-exact values are needed, e.g. for
+    /// verifying signatures.
+    pub fn header_raw(&self, name: &str) -> Option<&str> {
+        self.raw_headers
+            .iter()
+            .rev()
+            .filter_map(|line| split_header(line))
+            .find(|(header_name, _)| header_name.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value)
+    }
+
+    /// Returns the headers serialized in a deterministic format,
+    /// e.g. for cache keys or signature bases.
+    ///
+    /// Each header is written as `name:value\n`, where the name is
+    /// lowercase and the value has its leading and trailing
+    /// whitespace trimmed. The headers are sorted by name, so the
+    /// result doesn't depend on the order the headers were received
+    /// in.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> Result<(), minreq::Error> {
+    /// let response = minreq::get("http://example.com").send()?;
+    /// // E.g. "content-length:1256\ncontent-type:text/html\n..."
+    /// println!("{}", response.canonical_headers());
+    /// # Ok(()) }
+    /// ```
+    pub fn canonical_headers(&self) -> String {
+        let mut headers = self
+            .headers
+            .iter()
+            .map(|(name, value)| (name.to_lowercase(), value.trim()))
+            .collect::<Vec<(String, &str)>>();
+        headers.sort_unstable();
+        let mut canonical = String::new();
+        for (name, value) in headers {
+            canonical.push_str(&name);
+            canonical.push(':');
+            canonical.push_str(value);
+            canonical.push('\n');
+        }
+        canonical
+    }
+
+    /// Parses the cookies set by the response's `Set-Cookie` headers.
+    /// Cookies which can't be parsed are left out.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> Result<(), minreq::Error> {
+    /// let response = minreq::get("http://example.com").send()?;
+    /// for cookie in response.cookies() {
+    ///     println!("{}={}", cookie.name, cookie.value);
+    /// }
+    /// # Ok(()) }
+    /// ```
+    pub fn cookies(&self) -> Vec<Cookie> {
+        self.raw_headers
+            .iter()
+            .filter_map(|line| split_header(line))
+            .filter(|(name, _)| name.eq_ignore_ascii_case("set-cookie"))
+            .filter_map(|(_, value)| parse_set_cookie(value))
+            .collect()
+    }
+
+    /// Returns the name of the TLS cipher suite negotiated for the
+    /// connection this response was received over, e.g.
+    /// "TLS13_AES_256_GCM_SHA384".
+    ///
+    /// Only available for HTTPS requests sent with the rustls
+    /// backend (the `https` and `https-rustls*` features), `None`
+    /// otherwise.
+    pub fn tls_cipher_suite(&self) -> Option<&str> {
+        self.tls_info
+            .as_ref()
+            .map(|info| info.cipher_suite.as_str())
+    }
+
+    /// Returns the TLS protocol version negotiated for the connection
+    /// this response was received over, e.g. "TLSv1_3".
+    ///
+    /// Only available for HTTPS requests sent with the rustls
+    /// backend (the `https` and `https-rustls*` features), `None`
+    /// otherwise.
+    pub fn tls_protocol_version(&self) -> Option<&str> {
+        self.tls_info
+            .as_ref()
+            .map(|info| info.protocol_version.as_str())
+    }
+
+    /// Returns how long each phase of the request took. If redirects
+    /// were followed, these are the timings of the last request.
+    pub fn timings(&self) -> Timings {
+        self.timings
+    }
+
+    /// Returns the status code the proxy responded to the `CONNECT`
+    /// request with, when the request was tunneled through a proxy,
+    /// i.e. an HTTPS request sent with
+    /// [`with_proxy`](struct.Request.html#method.with_proxy). `None`
+    /// otherwise, including for requests sent through SOCKS5 proxies.
+    pub fn proxy_connect_status(&self) -> Option<i32> {
+        self.proxy_connect_status
+    }
+
+    /// Returns the name of the environment variable (e.g.
+    /// `http_proxy` or `ALL_PROXY`) the proxy was picked up from, if
+    /// the request was sent through a proxy from the environment
+    /// rather than one set with
+    /// [`with_proxy`](struct.Request.html#method.with_proxy).
+    pub fn proxy_source(&self) -> Option<&str> {
+        self.proxy_source
+    }
+
+    /// Returns true if the connection the response was received over
+    /// was made to an IPv6 address. When the request was sent through
+    /// a proxy, this is the address of the proxy.
+    pub fn used_ipv6(&self) -> bool {
+        self.used_ipv6
+    }
+
+    /// Returns the value of type `T` attached to the request with
+    /// [`with_extension`](struct.Request.html#method.with_extension),
+    /// if any.
+    pub fn extension<T: Any + Send + Sync>(&self) -> Option<&T> {
+        self.extensions.get()
+    }
+
+    /// Parses the authentication challenges in the response's
+    /// `WWW-Authenticate` header, usually sent along with a `401
+    /// Unauthorized` status. Returns an empty `Vec` if there is no
+    /// such header.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> Result<(), minreq::Error> {
+    /// let response = minreq::get("http://example.com/protected").send()?;
+    /// for challenge in response.auth_challenges() {
+    ///     println!("{} realm: {:?}", challenge.scheme, challenge.params.get("realm"));
+    /// }
+    /// # Ok(()) }
+    /// ```
+    pub fn auth_challenges(&self) -> Vec<AuthChallenge> {
+        self.header_values("www-authenticate")
+            .flat_map(parse_challenges)
+            .collect()
+    }
+
     /// Converts JSON body to a `struct` using Serde.
     ///
     /// # Errors
@@ -166,6 +565,138 @@ impl Response {
             Err(err) => Err(Error::SerdeJsonError(err)),
         }
     }
+
+    /// Returns true if the `Content-Type` of the response is JSON:
+    /// `application/json`, or a type with the `+json` suffix, like
+    /// `application/problem+json`.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> Result<(), minreq::Error> {
+    /// let response = minreq::get("http://example.com/api").send()?;
+    /// if !response.is_json() {
+    ///     println!("expected JSON, got: {}", response.as_str()?);
+    /// }
+    /// # Ok(()) }
+    /// ```
+    pub fn is_json(&self) -> bool {
+        self.media_type().map_or(false, |media_type| {
+            media_type == "application/json" || media_type.ends_with("+json")
+        })
+    }
+
+    /// Returns true if the `Content-Type` of the response is
+    /// `text/html`.
+    pub fn is_html(&self) -> bool {
+        self.media_type()
+            .map_or(false, |media_type| media_type == "text/html")
+    }
+
+    /// Returns true if the `Content-Type` of the response is any
+    /// `text/*` type, e.g. `text/plain` or `text/html`.
+    pub fn is_text(&self) -> bool {
+        self.media_type()
+            .map_or(false, |media_type| media_type.starts_with("text/"))
+    }
+
+    /// Returns the `Content-Type` without its parameters, lowercased,
+    /// e.g. "text/html" for "text/HTML; charset=utf-8".
+    fn media_type(&self) -> Option<String> {
+        let content_type = self.headers.get("content-type")?;
+        let media_type = content_type.split(';').next().unwrap_or("");
+        Some(media_type.trim().to_ascii_lowercase())
+    }
+
+    /// Returns the body decoded into a string, from the charset given
+    /// in the response's `Content-Type` header. If there's no
+    /// charset, or it isn't recognized, the body is decoded as UTF-8.
+    /// A byte order mark overrides the charset.
+    ///
+    /// Unlike [`as_str`](#method.as_str), this never fails: malformed
+    /// sequences are replaced with U+FFFD REPLACEMENT CHARACTER.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> Result<(), minreq::Error> {
+    /// # let url = "http://example.org/";
+    /// let response = minreq::get(url)
+    ///     .with_accept_charset(&["ISO-8859-1", "utf-8"])
+    ///     .send()?;
+    /// println!("{}", response.text());
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "encoding")]
+    pub fn text(&self) -> std::borrow::Cow<'_, str> {
+        let encoding = self
+            .headers
+            .get("content-type")
+            .and_then(|content_type| charset(content_type))
+            .and_then(|charset| encoding_rs::Encoding::for_label(charset.as_bytes()))
+            .unwrap_or(encoding_rs::UTF_8);
+        let (text, _, _) = encoding.decode(&self.body);
+        text
+    }
+
+    /// Parses the body as `application/x-www-form-urlencoded`, into
+    /// its key-value pairs in the order they appear. Keys and values
+    /// are percent-decoded, and a key without a `=` has an empty
+    /// value.
+    ///
+    /// # Errors
+    ///
+    /// Returns
+    /// [`InvalidUtf8InBody`](enum.Error.html#variant.InvalidUtf8InBody)
+    /// if the body, or a decoded key or value, is not UTF-8.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> Result<(), minreq::Error> {
+    /// # let url = "http://example.org/";
+    /// for (key, value) in minreq::get(url).send()?.form()? {
+    ///     println!("{} = {}", key, value);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "urlencoding")]
+    pub fn form(&self) -> Result<Vec<(String, String)>, Error> {
+        let decode = |encoded: &str| -> Result<String, Error> {
+            match urlencoding::decode(&encoded.replace('+', " ")) {
+                Ok(decoded) => Ok(decoded.into_owned()),
+                Err(err) => Err(Error::InvalidUtf8InBody(err.utf8_error())),
+            }
+        };
+        self.as_str()?
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (key, value) = match pair.find('=') {
+                    Some(i) => (&pair[..i], &pair[i + 1..]),
+                    None => (pair, ""),
+                };
+                Ok((decode(key)?, decode(value)?))
+            })
+            .collect()
+    }
+}
+
+/// Returns the value of the `charset` parameter of a `Content-Type`
+/// header, without quotes.
+#[cfg(feature = "encoding")]
+fn charset(content_type: &str) -> Option<&str> {
+    content_type.split(';').skip(1).find_map(|param| {
+        let param = param.trim();
+        let i = param.find('=')?;
+        if param[..i].trim().eq_ignore_ascii_case("charset") {
+            Some(param[i + 1..].trim().trim_matches('"'))
+        } else {
+            None
+        }
+    })
 }
 
 /// An HTTP response, which is loaded lazily.
@@ -181,12 +712,22 @@ impl Response {
 /// a `Result<(u8, usize), minreq::Error>`, as the reading operation
 /// can fail in various ways. The `u8` is the actual byte that was
 /// read, and `usize` is how many bytes we are expecting to read in
-/// the future (including this byte). Note, however, that the `usize`
-/// can change, particularly when the `Transfer-Encoding` is
-/// `chunked`: then it will reflect how many bytes are left of the
-/// current chunk. The expected size is capped at 16 KiB to avoid
-/// server-side DoS attacks targeted at clients accidentally reserving
-/// too much memory.
+/// the future (including this byte). What that means depends on how
+/// the body's length is specified:
+///
+/// - With a `Content-Length`, it's the amount of bytes left in the
+///   body, so the last byte of the body has a `usize` of 1.
+/// - With a `chunked` `Transfer-Encoding`, it's the amount of bytes
+///   left in the current chunk. The last byte of each chunk has a
+///   `usize` of 1, and the first byte of the next chunk has the size
+///   of that chunk, so a chunk boundary is crossed after each byte
+///   with a `usize` of 1.
+/// - Otherwise, the length of the body is not known, and the `usize`
+///   is always 1.
+///
+/// The expected size is capped at 16 KiB to avoid server-side DoS
+/// attacks targeted at clients accidentally reserving too much
+/// memory.
 ///
 /// # Example
 /// ```no_run
@@ -217,60 +758,629 @@ pub struct ResponseLazy {
     /// <http://example.com?foo=bar> would be corrected to
     /// <http://example.com/?foo=bar>).
     pub url: String,
+    /// The amount of redirections that were followed to get this
+    /// response.
+    pub redirect_count: usize,
 
+    raw_headers: Vec<String>,
+    pub(crate) tls_info: Option<TlsInfo>,
+    pub(crate) timings: Timings,
+    pub(crate) proxy_connect_status: Option<i32>,
+    pub(crate) proxy_source: Option<&'static str>,
+    pub(crate) used_ipv6: bool,
+    pub(crate) extensions: Extensions,
     stream: HttpStreamBytes,
     state: HttpStreamState,
     max_trailing_headers_size: Option<usize>,
+    strict_parsing: bool,
+    keep_alive: bool,
+    is_head: bool,
+    max_response_size: Option<usize>,
+    bytes_read: usize,
+    peeked_bytes: Vec<u8>,
+    peeked_lengths: Vec<usize>,
+    peek_index: usize,
+    #[cfg(feature = "decompress")]
+    pub(crate) decoder: Option<Decoder>,
+}
+
+/// A byte iterator over a buffered [`HttpStream`], like
+/// [`io::Bytes`], except that the stream can be taken back out.
+struct HttpStreamBytes {
+    reader: BufReader<HttpStream>,
+}
+
+impl HttpStreamBytes {
+    fn new(stream: HttpStream) -> HttpStreamBytes {
+        HttpStreamBytes {
+            reader: BufReader::with_capacity(BACKING_READ_BUFFER_LENGTH, stream),
+        }
+    }
+
+    /// Returns the stream, if no bytes are left over in the buffer.
+    fn into_inner(self) -> Option<HttpStream> {
+        if self.reader.buffer().is_empty() {
+            Some(self.reader.into_inner())
+        } else {
+            None
+        }
+    }
 }
 
-type HttpStreamBytes = Bytes<BufReader<HttpStream>>;
+impl Iterator for HttpStreamBytes {
+    type Item = io::Result<u8>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            return match self.reader.fill_buf() {
+                Ok([]) => None,
+                Ok(buf) => {
+                    let byte = buf[0];
+                    self.reader.consume(1);
+                    Some(Ok(byte))
+                }
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => Some(Err(err)),
+            };
+        }
+    }
+}
 
 impl ResponseLazy {
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn from_stream(
         stream: HttpStream,
         max_headers_size: Option<usize>,
+        max_trailer_size: Option<usize>,
         max_status_line_len: Option<usize>,
+        max_response_size: Option<usize>,
+        strict_parsing: bool,
+        force_close_delimited: bool,
+        is_head: bool,
     ) -> Result<ResponseLazy, Error> {
-        let mut stream = BufReader::with_capacity(BACKING_READ_BUFFER_LENGTH, stream).bytes();
+        let mut stream = HttpStreamBytes::new(stream);
         let ResponseMetadata {
             status_code,
             reason_phrase,
             headers,
+            raw_headers,
             state,
             max_trailing_headers_size,
-        } = read_metadata(&mut stream, max_headers_size, max_status_line_len)?;
+            keep_alive,
+        } = read_metadata(
+            &mut stream,
+            max_headers_size,
+            max_status_line_len,
+            strict_parsing,
+            force_close_delimited,
+            is_head,
+        )?;
+        stream.reader.get_mut().end_headers_timeout();
+        let max_trailing_headers_size = max_trailer_size.or(max_trailing_headers_size);
+
+        if strict_parsing && is_head && !stream.reader.buffer().is_empty() {
+            // Only catches bodies sent along with the headers, but
+            // waiting for one to maybe arrive later isn't an option.
+            return Err(Error::UnexpectedBody);
+        }
+
+        #[cfg(feature = "decompress")]
+        let decoder = headers
+            .get("content-encoding")
+            .and_then(|coding| Decoder::new(coding));
 
         Ok(ResponseLazy {
             status_code,
             reason_phrase,
             headers,
             url: String::new(),
+            redirect_count: 0,
+            raw_headers,
+            tls_info: None,
+            timings: Timings::default(),
+            proxy_connect_status: None,
+            proxy_source: None,
+            used_ipv6: false,
+            extensions: Extensions::default(),
             stream,
             state,
             max_trailing_headers_size,
+            strict_parsing,
+            keep_alive,
+            is_head,
+            max_response_size,
+            bytes_read: 0,
+            peeked_bytes: Vec::new(),
+            peeked_lengths: Vec::new(),
+            peek_index: 0,
+            #[cfg(feature = "decompress")]
+            decoder,
         })
     }
-}
 
-impl Iterator for ResponseLazy {
-    type Item = Result<(u8, usize), Error>;
-
-    fn next(&mut self) -> Option<Self::Item> {
+    /// Returns the underlying connection, so that it can be reused
+    /// for another request, or `None` if it can't be reused.
+    ///
+    /// The connection can only be reused if the server did not ask
+    /// for it to be closed, and the body of the response has been
+    /// read fully, which requires that it had a `Content-Length` or
+    /// was chunked. The body is not read by this function, so make
+    /// sure to iterate through this `ResponseLazy` until it ends
+    /// before calling this.
+    ///
+    /// The returned [`HttpStream`](struct.HttpStream.html) is the
+    /// raw connection: the next request needs to be written into it
+    /// as bytes, and its response parsed by you. The timeout of the
+    /// original request still applies to it.
+    pub fn into_stream(self) -> Option<HttpStream> {
         use HttpStreamState::*;
+        let body_read = match self.state {
+            _ if self.is_head => true,
+            ContentLength(length) => length == 0,
+            Chunked(expecting_chunks, length, _) => !expecting_chunks && length == 0,
+            EndOnClose => false,
+        };
+        let peeked_all = self.peek_index == self.peeked_bytes.len();
+        if self.keep_alive && body_read && peeked_all {
+            self.stream.into_inner()
+        } else {
+            None
+        }
+    }
+
+    /// Returns the next `n` bytes of the body without consuming
+    /// them, so they will still be returned when iterating or reading
+    /// this `ResponseLazy`. Useful for e.g. checking the magic bytes
+    /// of a file format before deciding how to handle the body.
+    ///
+    /// The returned slice is shorter than `n` if the body ends
+    /// before that. Like iterating, this decodes chunked bodies and
+    /// doesn't read past the end of the body.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as iterating over this `ResponseLazy`
+    /// would. The bytes peeked before the error are kept.
+    pub fn peek(&mut self, n: usize) -> Result<&[u8], Error> {
+        while self.peeked_bytes.len() - self.peek_index < n {
+            match self.read_body_byte() {
+                Some(Ok((byte, length))) => {
+                    self.peeked_bytes.push(byte);
+                    self.peeked_lengths.push(length);
+                }
+                Some(Err(err)) => return Err(err),
+                None => break,
+            }
+        }
+        let end = self.peeked_bytes.len().min(self.peek_index + n);
+        Ok(&self.peeked_bytes[self.peek_index..end])
+    }
+
+    /// Reads some of the body into `buf`, like [`Read::read`], but
+    /// gives up if no data arrives within `timeout`, regardless of the
+    /// request's timeout. Useful for e.g. doing other work between
+    /// reads, without giving up on a slow response altogether.
+    ///
+    /// Unlike `Read::read`, this returns as soon as some bytes have
+    /// been read and no more are immediately available, instead of
+    /// waiting for `buf` to be filled.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error with the kind
+    /// [`WouldBlock`](std::io::ErrorKind::WouldBlock) if `timeout`
+    /// passed without any data arriving. The connection is left
+    /// open, so the read can be tried again, except for chunked
+    /// bodies in the rare case where the timeout interrupts a chunk
+    /// size line or the trailers. If the request's own timeout is
+    /// reached first, the error has the kind
+    /// [`TimedOut`](std::io::ErrorKind::TimedOut) as usual.
+    pub fn read_with_timeout(&mut self, buf: &mut [u8], timeout: Duration) -> io::Result<usize> {
+        self.stream.reader.get_mut().read_call_timeout = Some(timeout);
+        let mut index = 0;
+        let result = loop {
+            if index >= buf.len() {
+                break Ok(index);
+            }
+            match self.next() {
+                Some(Ok((byte, _))) => {
+                    buf[index] = byte;
+                    index += 1;
+                    let peeked_all = self.peek_index == self.peeked_bytes.len();
+                    if peeked_all && self.stream.reader.buffer().is_empty() {
+                        break Ok(index);
+                    }
+                }
+                Some(Err(err)) => break Err(into_io_error(err)),
+                None => break Ok(index),
+            }
+        };
+        self.stream.reader.get_mut().read_call_timeout = None;
+        result
+    }
+
+    /// Returns an iterator which reads the body as newline-delimited
+    /// JSON (NDJSON), deserializing each line into a `T` as it
+    /// arrives. Blank lines are skipped, and the last line doesn't
+    /// need to end in a newline.
+    ///
+    /// The iterator ends after the first error reading the response,
+    /// but a line which fails to deserialize only produces an error
+    /// for that line.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use serde_json::Value;
+    ///
+    /// # fn main() -> Result<(), minreq::Error> {
+    /// let response = minreq::get("http://example.com/events").send_lazy()?;
+    /// for event in response.json_lines::<Value>() {
+    ///     println!("{}", event?);
+    /// }
+    /// # Ok(()) }
+    /// ```
+    #[cfg(feature = "json-using-serde")]
+    pub fn json_lines<T>(mut self) -> impl Iterator<Item = Result<T, Error>>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let mut done = false;
+        std::iter::from_fn(move || {
+            while !done {
+                let mut line = Vec::new();
+                loop {
+                    match self.next() {
+                        Some(Ok((b'\n', _))) => break,
+                        Some(Ok((byte, _))) => line.push(byte),
+                        Some(Err(err)) => {
+                            done = true;
+                            return Some(Err(err));
+                        }
+                        None => {
+                            done = true;
+                            break;
+                        }
+                    }
+                }
+                if !line.iter().all(u8::is_ascii_whitespace) {
+                    return Some(serde_json::from_slice(&line).map_err(Error::SerdeJsonError));
+                }
+            }
+            None
+        })
+    }
+
+    /// Reads the rest of the body into a `Vec`, like
+    /// [`Read::read_to_end`], but fails with
+    /// [`Error::ResponseTooLarge`] instead if the body is longer than
+    /// `max` bytes. Meant for buffering responses from untrusted
+    /// servers without risking running out of memory, see
+    /// [`Request::with_max_response_size`](struct.Request.html#method.with_max_response_size)
+    /// for setting a limit for the whole request instead.
+    ///
+    /// If the `Content-Length` of the body is already over `max`, the
+    /// error is returned without reading the body.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> Result<(), minreq::Error> {
+    /// let response = minreq::get("http://example.com").send_lazy()?;
+    /// let body = response.read_to_end_capped(1024 * 1024)?;
+    /// # Ok(()) }
+    /// ```
+    pub fn read_to_end_capped(self, max: usize) -> Result<Vec<u8>, Error> {
+        if let Some(length) = self.known_body_length() {
+            if length > max {
+                return Err(Error::ResponseTooLarge);
+            }
+        }
+        let mut body = Vec::new();
+        for byte in self {
+            let (byte, length) = byte?;
+            if body.len() >= max {
+                return Err(Error::ResponseTooLarge);
+            }
+            body.reserve(length.min(max - body.len()));
+            body.push(byte);
+        }
+        Ok(body)
+    }
+
+    /// Writes the body into the file at `path`, creating it or
+    /// replacing its contents, and returns the length of the body.
+    /// Meant for downloads too large to keep in memory.
+    ///
+    /// If the length of the body is known up front from the
+    /// `Content-Length` header, the file is allocated to that size and
+    /// memory-mapped, and the body is read straight into the mapping.
+    /// Otherwise, i.e. for chunked, close-delimited and decompressed
+    /// bodies, the file is written to normally as the body is read.
+    ///
+    /// `expected_len` is the largest body you're expecting: longer
+    /// bodies fail with [`Error::BodyOverflow`], before anything is
+    /// allocated if possible. A body which ends before its
+    /// `Content-Length` results in an [`Error::IoError`]. After an
+    /// error, the file may contain a partial body.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # fn main() -> Result<(), minreq::Error> {
+    /// let response = minreq::get("http://example.com/big.iso").send_lazy()?;
+    /// let length = response.save_to_mmap("big.iso", 8 * 1024 * 1024 * 1024)?;
+    /// println!("downloaded {} bytes", length);
+    /// # Ok(()) }
+    /// ```
+    #[cfg(feature = "mmap")]
+    pub fn save_to_mmap<P: AsRef<Path>>(
+        mut self,
+        path: P,
+        expected_len: u64,
+    ) -> Result<u64, Error> {
+        use std::fs::OpenOptions;
+        use std::io::{BufWriter, Write};
+
+        let length = self.known_body_length();
+        if let Some(length) = length {
+            if length as u64 > expected_len {
+                return Err(Error::BodyOverflow);
+            }
+        }
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+
+        let length = match length {
+            Some(length) => length,
+            None => {
+                let mut writer = BufWriter::new(file);
+                let mut written = 0;
+                for byte in self {
+                    let (byte, _) = byte?;
+                    written += 1;
+                    if written > expected_len {
+                        return Err(Error::BodyOverflow);
+                    }
+                    writer.write_all(&[byte])?;
+                }
+                writer.flush()?;
+                return Ok(written);
+            }
+        };
+
+        file.set_len(length as u64)?;
+        if length == 0 {
+            // Empty files can't be mapped.
+            return Ok(0);
+        }
+        // SAFETY: the file was just created by us, and the mapping
+        // doesn't outlive this function. Like with any memory-mapped
+        // file, other processes modifying the file at the same time
+        // is undefined behavior.
+        let mut map = unsafe { memmap2::MmapMut::map_mut(&file)? };
+        for slot in map.iter_mut() {
+            match self.next() {
+                Some(Ok((byte, _))) => *slot = byte,
+                Some(Err(err)) => return Err(err),
+                None => {
+                    return Err(Error::IoError(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "the body ended before its Content-Length",
+                    )))
+                }
+            }
+        }
+        map.flush()?;
+        Ok(length as u64)
+    }
+
+    /// Returns the length of the rest of the body, if it's known
+    /// before reading it. Responses to `HEAD` requests have no body,
+    /// whatever their `Content-Length` says.
+    fn known_body_length(&self) -> Option<usize> {
+        if self.is_head {
+            return Some(0);
+        }
+        #[cfg(feature = "decompress")]
+        if self.decoder.is_some() {
+            return None;
+        }
         match self.state {
+            HttpStreamState::ContentLength(length) => {
+                Some(length + self.peeked_bytes.len() - self.peek_index)
+            }
+            _ => None,
+        }
+    }
+
+    /// Reads the next byte of the body, decompressed if needed.
+    fn read_body_byte(&mut self) -> Option<<ResponseLazy as Iterator>::Item> {
+        #[cfg(feature = "decompress")]
+        if self.decoder.is_some() {
+            return self.read_decoded_byte();
+        }
+        self.read_byte()
+    }
+
+    /// Reads the next byte of a compressed body, decompressing more
+    /// of it when the previously decoded bytes have been read.
+    /// `max_response_size` applies to the decompressed body too.
+    #[cfg(feature = "decompress")]
+    fn read_decoded_byte(&mut self) -> Option<<ResponseLazy as Iterator>::Item> {
+        loop {
+            let decoder = self.decoder.as_mut()?;
+            if let Some((byte, length)) = decoder.next_byte() {
+                if let Some(max_response_size) = self.max_response_size {
+                    if decoder.bytes_out > max_response_size {
+                        return Some(Err(Error::BodyOverflow));
+                    }
+                }
+                return Some(Ok((byte, length)));
+            }
+            if decoder.is_finished() {
+                return None;
+            }
+
+            // Decode what has already been received, waiting for more
+            // only if nothing has.
+            let mut compressed = Vec::new();
+            let mut body_ended = false;
+            while compressed.len() < BACKING_READ_BUFFER_LENGTH {
+                match self.read_byte() {
+                    Some(Ok((byte, _))) => compressed.push(byte),
+                    Some(Err(err)) => return Some(Err(err)),
+                    None => {
+                        body_ended = true;
+                        break;
+                    }
+                }
+                if self.stream.reader.buffer().is_empty() {
+                    break;
+                }
+            }
+
+            let decoder = self.decoder.as_mut()?;
+            let mut result = decoder.write(&compressed);
+            if body_ended && result.is_ok() {
+                result = decoder.finish();
+            }
+            if let Err(err) = result {
+                return Some(Err(Error::IoError(err)));
+            }
+        }
+    }
+
+    fn read_byte(&mut self) -> Option<<ResponseLazy as Iterator>::Item> {
+        use HttpStreamState::*;
+        if let Some(max_response_size) = self.max_response_size {
+            if self.bytes_read > max_response_size {
+                return Some(Err(Error::BodyOverflow));
+            }
+        }
+        let result = match self.state {
             EndOnClose => read_until_closed(&mut self.stream),
             ContentLength(ref mut length) => read_with_content_length(&mut self.stream, length),
             Chunked(ref mut expecting_chunks, ref mut length, ref mut content_length) => {
                 read_chunked(
                     &mut self.stream,
                     &mut self.headers,
+                    &mut self.raw_headers,
                     expecting_chunks,
                     length,
                     content_length,
                     self.max_trailing_headers_size,
+                    self.strict_parsing,
                 )
             }
+        };
+        if let Some(Ok(_)) = result {
+            self.bytes_read += 1;
+            if let Some(max_response_size) = self.max_response_size {
+                if self.bytes_read > max_response_size {
+                    return Some(Err(Error::BodyOverflow));
+                }
+            }
+        }
+        result
+    }
+}
+
+impl ResponseLazy {
+    /// Returns the name of the TLS cipher suite negotiated for the
+    /// connection, see
+    /// [`Response::tls_cipher_suite`](struct.Response.html#method.tls_cipher_suite).
+    pub fn tls_cipher_suite(&self) -> Option<&str> {
+        self.tls_info
+            .as_ref()
+            .map(|info| info.cipher_suite.as_str())
+    }
+
+    /// Returns the TLS protocol version negotiated for the
+    /// connection, see
+    /// [`Response::tls_protocol_version`](struct.Response.html#method.tls_protocol_version).
+    pub fn tls_protocol_version(&self) -> Option<&str> {
+        self.tls_info
+            .as_ref()
+            .map(|info| info.protocol_version.as_str())
+    }
+
+    /// Returns how long each phase of the request took, see
+    /// [`Response::timings`](struct.Response.html#method.timings).
+    pub fn timings(&self) -> Timings {
+        self.timings
+    }
+
+    /// Returns the status code the proxy responded to the `CONNECT`
+    /// request with, see
+    /// [`Response::proxy_connect_status`](struct.Response.html#method.proxy_connect_status).
+    pub fn proxy_connect_status(&self) -> Option<i32> {
+        self.proxy_connect_status
+    }
+
+    /// Returns the environment variable the proxy was picked up from,
+    /// see
+    /// [`Response::proxy_source`](struct.Response.html#method.proxy_source).
+    pub fn proxy_source(&self) -> Option<&str> {
+        self.proxy_source
+    }
+
+    /// Returns true if the connection was made to an IPv6 address,
+    /// see [`Response::used_ipv6`](struct.Response.html#method.used_ipv6).
+    pub fn used_ipv6(&self) -> bool {
+        self.used_ipv6
+    }
+
+    /// Returns the value of type `T` attached to the request, see
+    /// [`Response::extension`](struct.Response.html#method.extension).
+    pub fn extension<T: Any + Send + Sync>(&self) -> Option<&T> {
+        self.extensions.get()
+    }
+}
+
+/// How long the phases of a request took, returned by
+/// [`Response::timings`](struct.Response.html#method.timings).
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Timings {
+    /// Resolving the host name into IP addresses. When using a
+    /// proxy, this is the proxy's host name.
+    pub dns_lookup: Duration,
+    /// Opening the TCP connection, including the `CONNECT` exchange
+    /// when tunneling through a proxy.
+    pub connect: Duration,
+    /// The TLS handshake, or `None` for plain HTTP requests.
+    pub tls_handshake: Option<Duration>,
+    /// From sending the request until the status line and headers
+    /// of the response were received.
+    pub first_byte: Duration,
+}
+
+/// The parameters negotiated for a TLS connection.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub(crate) struct TlsInfo {
+    pub(crate) cipher_suite: String,
+    pub(crate) protocol_version: String,
+}
+
+impl Iterator for ResponseLazy {
+    type Item = Result<(u8, usize), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(&byte) = self.peeked_bytes.get(self.peek_index) {
+            let length = self.peeked_lengths[self.peek_index];
+            self.peek_index += 1;
+            if self.peek_index == self.peeked_bytes.len() {
+                self.peeked_bytes.clear();
+                self.peeked_lengths.clear();
+                self.peek_index = 0;
+            }
+            return Some(Ok((byte, length)));
         }
+        self.read_body_byte()
     }
 }
 
@@ -280,10 +1390,7 @@ impl Read for ResponseLazy {
         for res in self {
             // there is no use for the estimated length in the read implementation
             // so it is ignored.
-            let (byte, _) = res.map_err(|e| match e {
-                Error::IoError(e) => e,
-                _ => io::Error::new(io::ErrorKind::Other, e),
-            })?;
+            let (byte, _) = res.map_err(into_io_error)?;
 
             buf[index] = byte;
             index += 1;
@@ -299,6 +1406,13 @@ impl Read for ResponseLazy {
     }
 }
 
+fn into_io_error(err: Error) -> io::Error {
+    match err {
+        Error::IoError(err) => err,
+        _ => io::Error::new(io::ErrorKind::Other, err),
+    }
+}
+
 fn read_until_closed(bytes: &mut HttpStreamBytes) -> Option<<ResponseLazy as Iterator>::Item> {
     if let Some(byte) = bytes.next() {
         match byte {
@@ -315,12 +1429,16 @@ fn read_with_content_length(
     content_length: &mut usize,
 ) -> Option<<ResponseLazy as Iterator>::Item> {
     if *content_length > 0 {
-        *content_length -= 1;
-
         if let Some(byte) = bytes.next() {
             match byte {
-                // Cap Content-Length to 16KiB, to avoid out-of-memory issues.
-                Ok(byte) => return Some(Ok((byte, (*content_length).min(MAX_CONTENT_LENGTH) + 1))),
+                Ok(byte) => {
+                    *content_length -= 1;
+                    // Cap Content-Length to 16KiB, to avoid out-of-memory issues.
+                    return Some(Ok((byte, (*content_length).min(MAX_CONTENT_LENGTH) + 1)));
+                }
+                // The length is only decremented for bytes actually
+                // read, so that the read can be retried after errors
+                // like timeouts.
                 Err(err) => return Some(Err(Error::IoError(err))),
             }
         }
@@ -331,15 +1449,23 @@ fn read_with_content_length(
 fn read_trailers(
     bytes: &mut HttpStreamBytes,
     headers: &mut HashMap<String, String>,
+    raw_headers: &mut Vec<String>,
     mut max_headers_size: Option<usize>,
+    strict_parsing: bool,
 ) -> Result<(), Error> {
     loop {
-        let trailer_line = read_line(bytes, max_headers_size, Error::HeadersOverflow)?;
+        let trailer_line = read_line(
+            bytes,
+            max_headers_size,
+            Error::HeadersOverflow,
+            strict_parsing,
+        )?;
         if let Some(ref mut max_headers_size) = max_headers_size {
             *max_headers_size -= trailer_line.len() + 2;
         }
-        if let Some((header, value)) = parse_header(trailer_line) {
+        if let Some((header, value)) = parse_header(&trailer_line, strict_parsing)? {
             headers.insert(header, value);
+            raw_headers.push(trailer_line);
         } else {
             break;
         }
@@ -347,13 +1473,16 @@ fn read_trailers(
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 fn read_chunked(
     bytes: &mut HttpStreamBytes,
     headers: &mut HashMap<String, String>,
+    raw_headers: &mut Vec<String>,
     expecting_more_chunks: &mut bool,
     chunk_length: &mut usize,
     content_length: &mut usize,
     max_trailing_headers_size: Option<usize>,
+    strict_parsing: bool,
 ) -> Option<<ResponseLazy as Iterator>::Item> {
     if !*expecting_more_chunks && *chunk_length == 0 {
         return None;
@@ -365,7 +1494,7 @@ fn read_chunked(
         // extensions (which are ignored).
 
         // Get the size of the next chunk
-        let length_line = match read_line(bytes, Some(1024), Error::MalformedChunkLength) {
+        let length_line = match read_line(bytes, Some(1024), Error::MalformedChunkLength, false) {
             Ok(line) => line,
             Err(err) => return Some(Err(err)),
         };
@@ -388,7 +1517,13 @@ fn read_chunked(
         };
 
         if incoming_length == 0 {
-            if let Err(err) = read_trailers(bytes, headers, max_trailing_headers_size) {
+            if let Err(err) = read_trailers(
+                bytes,
+                headers,
+                raw_headers,
+                max_trailing_headers_size,
+                strict_parsing,
+            ) {
                 return Some(Err(err));
             }
 
@@ -414,7 +1549,8 @@ fn read_chunked(
                         // TODO: Maybe this could be written in a way
                         // that doesn't discard the last ok byte if
                         // the \r\n reading fails?
-                        if let Err(err) = read_line(bytes, Some(2), Error::MalformedChunkEnd) {
+                        if let Err(err) = read_line(bytes, Some(2), Error::MalformedChunkEnd, false)
+                        {
                             return Some(Err(err));
                         }
                     }
@@ -452,21 +1588,37 @@ struct ResponseMetadata {
     status_code: i32,
     reason_phrase: String,
     headers: HashMap<String, String>,
+    raw_headers: Vec<String>,
     state: HttpStreamState,
     max_trailing_headers_size: Option<usize>,
+    keep_alive: bool,
 }
 
 fn read_metadata(
     stream: &mut HttpStreamBytes,
     mut max_headers_size: Option<usize>,
     max_status_line_len: Option<usize>,
+    strict_parsing: bool,
+    force_close_delimited: bool,
+    is_head: bool,
 ) -> Result<ResponseMetadata, Error> {
-    let line = read_line(stream, max_status_line_len, Error::StatusLineOverflow)?;
-    let (status_code, reason_phrase) = parse_status_line(&line);
+    let line = read_head_line(
+        stream,
+        max_status_line_len,
+        Error::StatusLineOverflow,
+        false,
+    )?;
+    let (status_code, reason_phrase) = parse_status_line(&line)?;
 
     let mut headers = HashMap::new();
+    let mut raw_headers = Vec::new();
     loop {
-        let line = read_line(stream, max_headers_size, Error::HeadersOverflow)?;
+        let line = read_head_line(
+            stream,
+            max_headers_size,
+            Error::HeadersOverflow,
+            strict_parsing,
+        )?;
         if line.is_empty() {
             // Body starts here
             break;
@@ -474,21 +1626,32 @@ fn read_metadata(
         if let Some(ref mut max_headers_size) = max_headers_size {
             *max_headers_size -= line.len() + 2;
         }
-        if let Some(header) = parse_header(line) {
+        if let Some(header) = parse_header(&line, strict_parsing)? {
             headers.insert(header.0, header.1);
+            raw_headers.push(line);
         }
     }
 
-    let mut chunked = false;
+    // The transfer codings, from all the Transfer-Encoding headers in
+    // order. "identity" means no coding, so it's left out.
+    let transfer_codings = raw_headers
+        .iter()
+        .filter_map(|line| split_header(line))
+        .filter(|(name, _)| name.eq_ignore_ascii_case("transfer-encoding"))
+        .flat_map(|(_, value)| value.split(','))
+        .map(|coding| coding.trim().to_lowercase())
+        .filter(|coding| !coding.is_empty() && coding != "identity")
+        .collect::<Vec<String>>();
+    // Following RFC 7230 section 3.3.3: if chunked is the final
+    // transfer coding, the body is chunked. If there are other
+    // codings, the body is delimited by closing the connection.
+    // Either way, Content-Length is ignored. With no codings (or only
+    // identity), Content-Length is used, if there is one.
+    let transfer_encoded = !transfer_codings.is_empty();
+    let chunked = transfer_codings.last().map(String::as_str) == Some("chunked");
+
     let mut content_length = None;
     for (header, value) in &headers {
-        // Handle the Transfer-Encoding header
-        if header.to_lowercase().trim() == "transfer-encoding"
-            && value.to_lowercase().trim() == "chunked"
-        {
-            chunked = true;
-        }
-
         // Handle the Content-Length header
         if header.to_lowercase().trim() == "content-length" {
             match str::parse::<usize>(value.trim()) {
@@ -498,29 +1661,107 @@ fn read_metadata(
         }
     }
 
-    let state = if chunked {
+    if strict_parsing {
+        // If the body length is specified in several different ways,
+        // intermediaries might disagree on which one is right, which
+        // can be used for request smuggling.
+        let mut content_lengths = raw_headers
+            .iter()
+            .filter_map(|line| split_header(line))
+            .filter(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+            .map(|(_, value)| value.trim());
+        if let Some(first) = content_lengths.next() {
+            if transfer_encoded || content_lengths.any(|value| value != first) {
+                return Err(Error::ConflictingBodyLength);
+            }
+        }
+    }
+
+    let state = if force_close_delimited {
+        HttpStreamState::EndOnClose
+    } else if chunked {
         HttpStreamState::Chunked(true, 0, 0)
+    } else if transfer_encoded {
+        HttpStreamState::EndOnClose
     } else if let Some(length) = content_length {
         HttpStreamState::ContentLength(length)
     } else {
         HttpStreamState::EndOnClose
     };
 
+    let connection = headers.get("connection").map(|value| value.to_lowercase());
+    let connection = connection.as_deref().unwrap_or("");
+    // A close-delimited body is only over once the connection is
+    // closed, so it can't be reused.
+    let keep_alive = if force_close_delimited {
+        false
+    } else if line.starts_with("HTTP/1.0") {
+        connection.contains("keep-alive")
+    } else {
+        !connection.contains("close")
+    };
+
+    if strict_parsing && !is_head {
+        if let HttpStreamState::EndOnClose = state {
+            // The body should be delimited by closing the connection,
+            // but if the server intends to keep it open, reading the
+            // body would hang until the server times out.
+            let has_body =
+                !(100..200).contains(&status_code) && status_code != 204 && status_code != 304;
+            if keep_alive && has_body {
+                return Err(Error::AmbiguousBodyLength);
+            }
+        }
+    }
+
     Ok(ResponseMetadata {
         status_code,
         reason_phrase,
         headers,
+        raw_headers,
         state,
         max_trailing_headers_size: max_headers_size,
+        keep_alive,
     })
 }
 
+/// Reads a line of the status line or headers, like [read_line], but
+/// returns [Error::ConnectionClosedDuringHeaders] if the connection
+/// is closed before the line ends, instead of the partial line.
+fn read_head_line(
+    stream: &mut HttpStreamBytes,
+    max_len: Option<usize>,
+    overflow_error: Error,
+    strict: bool,
+) -> Result<String, Error> {
+    match read_line_until_closed(stream, max_len, overflow_error, strict)? {
+        (line, true) => Ok(line),
+        (_, false) => Err(Error::ConnectionClosedDuringHeaders),
+    }
+}
+
+/// Reads a line ending in CRLF, or a bare LF. If `strict` is set, a
+/// bare LF is an error instead. If the connection is closed first,
+/// the line read until then is returned.
 fn read_line(
     stream: &mut HttpStreamBytes,
     max_len: Option<usize>,
     overflow_error: Error,
+    strict: bool,
 ) -> Result<String, Error> {
+    read_line_until_closed(stream, max_len, overflow_error, strict).map(|(line, _)| line)
+}
+
+/// Reads a line like [read_line], along with whether the line ended,
+/// rather than the connection being closed.
+fn read_line_until_closed(
+    stream: &mut HttpStreamBytes,
+    max_len: Option<usize>,
+    overflow_error: Error,
+    strict: bool,
+) -> Result<(String, bool), Error> {
     let mut bytes = Vec::with_capacity(32);
+    let mut ended = false;
     for byte in stream {
         match byte {
             Ok(byte) => {
@@ -532,7 +1773,10 @@ fn read_line(
                 if byte == b'\n' {
                     if let Some(b'\r') = bytes.last() {
                         bytes.pop();
+                    } else if strict {
+                        return Err(Error::MalformedHeader);
                     }
+                    ended = true;
                     break;
                 } else {
                     bytes.push(byte);
@@ -541,10 +1785,11 @@ fn read_line(
             Err(err) => return Err(Error::IoError(err)),
         }
     }
-    String::from_utf8(bytes).map_err(|_error| Error::InvalidUtf8InResponse)
+    let line = String::from_utf8(bytes).map_err(|_error| Error::InvalidUtf8InResponse)?;
+    Ok((line, ended))
 }
 
-fn parse_status_line(line: &str) -> (i32, String) {
+fn parse_status_line(line: &str) -> Result<(i32, String), Error> {
     // sample status line format
     // HTTP/1.1 200 OK
     let mut status_code = String::with_capacity(3);
@@ -564,34 +1809,192 @@ fn parse_status_line(line: &str) -> (i32, String) {
         }
     }
 
-    if let Ok(status_code) = status_code.parse::<i32>() {
-        return (status_code, reason_phrase);
+    match status_code.parse::<i32>() {
+        Ok(status_code) => Ok((status_code, reason_phrase)),
+        Err(_) => Err(Error::MalformedStatusLine),
     }
-
-    (503, "Server did not provide a status line".to_string())
 }
 
-fn parse_header(mut line: String) -> Option<(String, String)> {
-    if let Some(location) = line.find(':') {
+/// Parses a header line into its name and value. If `strict` is set,
+/// values containing control characters (other than tabs) are
+/// rejected, as e.g. a bare CR might be an attempt at response
+/// splitting, or get mishandled by whatever uses the header next.
+fn parse_header(line: &str, strict: bool) -> Result<Option<(String, String)>, Error> {
+    if strict && line.starts_with(|c| c == ' ' || c == '\t') {
+        // An obsolete line folding, i.e. a continuation of the
+        // previous header's value.
+        return Err(Error::MalformedHeader);
+    }
+    if let Some((name, raw_value)) = split_header(line) {
         // Trim the first character of the header if it is a space,
         // otherwise return everything after the ':'. This should
         // preserve the behavior in versions <=2.0.1 in most cases
         // (namely, ones where it was valid), where the first
         // character after ':' was always cut off.
-        let value = if let Some(sp) = line.get(location + 1..location + 2) {
-            if sp == " " {
-                line[location + 2..].to_string()
-            } else {
-                line[location + 1..].to_string()
-            }
-        } else {
-            line[location + 1..].to_string()
-        };
+        let value = raw_value.strip_prefix(' ').unwrap_or(raw_value);
+
+        if strict && value.chars().any(|c| c != '\t' && c.is_ascii_control()) {
+            return Err(Error::MalformedHeader);
+        }
 
-        line.truncate(location);
         // Headers should be ascii, I'm pretty sure. If not, please open an issue.
-        line.make_ascii_lowercase();
-        return Some((line, value));
+        return Ok(Some((name.to_ascii_lowercase(), value.to_string())));
+    }
+    Ok(None)
+}
+
+/// Splits a header line into its name and value, exactly as they were
+/// received.
+fn split_header(line: &str) -> Option<(&str, &str)> {
+    let location = line.find(':')?;
+    Some((&line[..location], &line[location + 1..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_header, Response};
+    use std::collections::HashMap;
+
+    fn response_with_headers(headers: &[(&str, &str)]) -> Response {
+        let raw_headers = headers
+            .iter()
+            .map(|(k, v)| format!("{}: {}", k, v))
+            .collect::<Vec<String>>();
+        let headers = headers
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<HashMap<String, String>>();
+        Response {
+            status_code: 200,
+            reason_phrase: "OK".to_string(),
+            headers,
+            url: "http://example.com/".to_string(),
+            redirect_count: 0,
+            raw_headers,
+            tls_info: None,
+            timings: Default::default(),
+            proxy_connect_status: None,
+            proxy_source: None,
+            used_ipv6: false,
+            extensions: Default::default(),
+            body: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn header_iteration() {
+        let response =
+            response_with_headers(&[("content-type", "text/plain"), ("content-length", "0")]);
+        assert_eq!(response.header_count(), 2);
+
+        let mut names = response.header_names().collect::<Vec<&str>>();
+        names.sort_unstable();
+        assert_eq!(names, vec!["content-length", "content-type"]);
+
+        let values = response
+            .header_values("Content-Type")
+            .collect::<Vec<&str>>();
+        assert_eq!(values, vec!["text/plain"]);
+        assert_eq!(response.header_values("x-missing").count(), 0);
+
+        let response = response_with_headers(&[("vary", "accept"), ("Vary", "origin")]);
+        let values = response.header_values("VARY").collect::<Vec<&str>>();
+        assert_eq!(values, vec!["accept", "origin"]);
+        assert_eq!(response.headers_all("vary"), values);
+    }
+
+    #[test]
+    fn parsed_headers() {
+        let response = response_with_headers(&[("age", " 120"), ("x-count", "many")]);
+        assert_eq!(response.header_parsed::<u64>("Age"), Some(Ok(120)));
+        assert!(matches!(
+            response.header_parsed::<u64>("x-count"),
+            Some(Err(_))
+        ));
+        assert_eq!(response.header_parsed::<u64>("x-missing"), None);
+    }
+
+    #[test]
+    fn canonical_headers() {
+        let a = response_with_headers(&[
+            ("content-type", "text/plain "),
+            ("x-b", "2"),
+            ("content-length", "0"),
+        ]);
+        let b = response_with_headers(&[
+            ("x-b", "2"),
+            ("content-length", " 0"),
+            ("content-type", "text/plain"),
+        ]);
+        assert_eq!(a.canonical_headers(), b.canonical_headers());
+        assert_eq!(
+            a.canonical_headers(),
+            "content-length:0\ncontent-type:text/plain\nx-b:2\n"
+        );
+        assert_eq!(response_with_headers(&[]).canonical_headers(), "");
+    }
+
+    #[test]
+    fn header_values_with_colons() {
+        let location = parse_header("Location: http://host:8080/", false).unwrap();
+        assert_eq!(
+            location,
+            Some(("location".to_string(), "http://host:8080/".to_string()))
+        );
+        let (_, value) = parse_header("X-Time:12:30:00", true).unwrap().unwrap();
+        assert_eq!(value, "12:30:00");
+    }
+
+    #[test]
+    fn content_type_families() {
+        let is = |content_type: &str| {
+            let response = response_with_headers(&[("content-type", content_type)]);
+            (response.is_json(), response.is_html(), response.is_text())
+        };
+        assert_eq!(is("application/json"), (true, false, false));
+        assert_eq!(is("Application/JSON; charset=utf-8"), (true, false, false));
+        assert_eq!(is("application/problem+json"), (true, false, false));
+        assert_eq!(is("text/html; charset=utf-8"), (false, true, true));
+        assert_eq!(is("text/plain"), (false, false, true));
+        assert_eq!(is("image/png"), (false, false, false));
+        assert_eq!(is("application/jsonp"), (false, false, false));
+
+        let response = response_with_headers(&[]);
+        assert!(!response.is_json() && !response.is_html() && !response.is_text());
+    }
+
+    #[test]
+    #[cfg(feature = "encoding")]
+    fn charset_parameter() {
+        use super::charset;
+        assert_eq!(charset("text/html; charset=ISO-8859-1"), Some("ISO-8859-1"));
+        assert_eq!(charset("text/html;CharSet=\"utf-8\" ; q=1"), Some("utf-8"));
+        assert_eq!(charset("text/html"), None);
+        assert_eq!(charset("charset=utf-8"), None);
+    }
+
+    #[test]
+    #[cfg(feature = "urlencoding")]
+    fn form_body() {
+        let mut response = response_with_headers(&[]);
+        response.body = b"name=J%C3%BCrgen+Smith&empty=&flag&sum=1%2B1%3D2&&q=a%26b".to_vec();
+        let form = response.form().unwrap();
+        let pairs = form
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect::<Vec<(&str, &str)>>();
+        assert_eq!(
+            pairs,
+            vec![
+                ("name", "J\u{fc}rgen Smith"),
+                ("empty", ""),
+                ("flag", ""),
+                ("sum", "1+1=2"),
+                ("q", "a&b"),
+            ]
+        );
+
+        response.body = b"bad=%FF".to_vec();
+        assert!(response.form().is_err());
     }
-    None
 }
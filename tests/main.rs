@@ -2,7 +2,7 @@ extern crate minreq;
 mod setup;
 
 use self::setup::*;
-use std::io;
+use std::io::{self, Read, Write};
 
 #[test]
 #[cfg(any(feature = "rustls", feature = "openssl", feature = "native-tls"))]
@@ -14,6 +14,121 @@ fn test_https() {
     );
 }
 
+#[test]
+#[cfg(feature = "rustls")]
+fn test_https_tls_info() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        tls_respond(&mut stream, b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n");
+    });
+    let response = minreq::get(format!("https://127.0.0.1:{}", port))
+        .with_rustls_config(tls_client_config())
+        .send()
+        .unwrap();
+    assert!(response.tls_cipher_suite().is_some());
+    assert!(response.tls_protocol_version().is_some());
+}
+
+#[test]
+#[cfg(feature = "rustls")]
+fn test_tls_session_resumption() {
+    use rustls::server::{ServerSessionMemoryCache, StoresServerSessions};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// Counts the sessions which were found in the cache, i.e. the
+    /// handshakes which resumed a session.
+    struct CountResumptions(Arc<dyn StoresServerSessions>, Arc<AtomicUsize>);
+    impl StoresServerSessions for CountResumptions {
+        fn put(&self, key: Vec<u8>, value: Vec<u8>) -> bool {
+            self.0.put(key, value)
+        }
+        fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+            self.count(self.0.get(key))
+        }
+        fn take(&self, key: &[u8]) -> Option<Vec<u8>> {
+            self.count(self.0.take(key))
+        }
+        fn can_cache(&self) -> bool {
+            self.0.can_cache()
+        }
+    }
+    impl CountResumptions {
+        fn count(&self, session: Option<Vec<u8>>) -> Option<Vec<u8>> {
+            if session.is_some() {
+                self.1.fetch_add(1, Ordering::SeqCst);
+            }
+            session
+        }
+    }
+
+    let resumptions = Arc::new(AtomicUsize::new(0));
+    let mut server_config = tls_server_config();
+    server_config.session_storage = Arc::new(CountResumptions(
+        ServerSessionMemoryCache::new(16),
+        resumptions.clone(),
+    ));
+    let server_config = Arc::new(server_config);
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    std::thread::spawn(move || {
+        for _ in 0..2 {
+            let (mut stream, _) = listener.accept().unwrap();
+            let response = b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n";
+            tls_respond_with(server_config.clone(), &mut stream, response);
+        }
+    });
+
+    // The session cache is part of the client config, which is shared
+    // by every request, like the default one is.
+    let url = format!("https://localhost:{}", port);
+    let client_config = tls_client_config();
+    for _ in 0..2 {
+        let request = minreq::get(&url).with_rustls_config(client_config.clone());
+        assert_eq!(request.send().unwrap().status_code, 200);
+    }
+    assert_eq!(resumptions.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+#[cfg(feature = "rustls")]
+fn test_rustls_config() {
+    // Reads the ClientHello, and checks whether it offers the
+    // protocol set in the custom configuration.
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let (sender, receiver) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        for _ in 0..2 {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut hello = vec![0; 4096];
+            let length = stream.read(&mut hello).unwrap();
+            let protocol = b"minreq-test";
+            let offered = hello[..length]
+                .windows(protocol.len())
+                .any(|w| w == protocol);
+            sender.send(offered).unwrap();
+        }
+    });
+    let url = format!("https://localhost:{}", port);
+
+    let mut config = rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(rustls::RootCertStore::empty())
+        .with_no_client_auth();
+    config.alpn_protocols = vec![b"minreq-test".to_vec()];
+    let config = std::sync::Arc::new(config);
+    let result = minreq::get(&url).with_rustls_config(config).send();
+    assert!(result.is_err());
+    assert!(receiver.recv().unwrap());
+
+    let result = minreq::get(&url).send();
+    assert!(result.is_err());
+    assert!(!receiver.recv().unwrap());
+}
+
 #[test]
 #[cfg(feature = "json-using-serde")]
 fn test_json_using_serde() {
@@ -32,6 +147,114 @@ fn test_json_using_serde() {
     assert_eq!(&actual_json, &original_json);
 }
 
+#[test]
+fn test_with_file() {
+    setup();
+    let path = std::env::temp_dir().join(format!("minreq-upload-{}.json", std::process::id()));
+    std::fs::write(&path, "{\"a\":1}").unwrap();
+    let request = minreq::post(url("/echo")).with_file(&path).unwrap();
+    let built = request.clone().build().unwrap();
+    let content_type = ("Content-Type".to_string(), "application/json".to_string());
+    assert!(built.headers.contains(&content_type));
+    let content_length = ("Content-Length".to_string(), "7".to_string());
+    assert!(built.headers.contains(&content_length));
+    assert_eq!(get_body(request.send()), "{\"a\":1}");
+    std::fs::remove_file(&path).unwrap();
+
+    let missing = minreq::post(url("/echo")).with_file(path);
+    assert!(matches!(missing, Err(minreq::Error::IoError(_))));
+}
+
+#[test]
+#[cfg(feature = "json-using-serde")]
+fn test_json_lines() {
+    let url = raw_server(|stream, _| {
+        let body = "{\"id\":1}\n{\"id\":2}\r\n\n{\"id\":3}";
+        let head = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", body.len());
+        stream.write_all(head.as_bytes()).unwrap();
+        stream.write_all(body.as_bytes()).unwrap();
+    });
+    let response = minreq::get(url).send_lazy().unwrap();
+    let ids = response
+        .json_lines::<serde_json::Value>()
+        .map(|value| value.unwrap()["id"].as_i64().unwrap())
+        .collect::<Vec<i64>>();
+    assert_eq!(ids, vec![1, 2, 3]);
+}
+
+#[test]
+#[cfg(feature = "mmap")]
+fn test_save_to_mmap() {
+    let body: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+    let sent = body.clone();
+    let sized = raw_server(move |stream, _| {
+        let head = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", sent.len());
+        stream.write_all(head.as_bytes()).unwrap();
+        stream.write_all(&sent).unwrap();
+    });
+    let chunked = raw_server(|stream, _| {
+        let head = "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n";
+        stream.write_all(head.as_bytes()).unwrap();
+        stream.write_all(b"5\r\nhello\r\n0\r\n\r\n").unwrap();
+    });
+    let too_long = raw_server(|stream, _| {
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\n0123456789")
+            .unwrap();
+    });
+    let head = raw_server(|stream, _| {
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\n")
+            .unwrap();
+    });
+    let path = std::env::temp_dir().join(format!("minreq-mmap-{}.bin", std::process::id()));
+
+    let response = minreq::get(sized).send_lazy().unwrap();
+    assert_eq!(
+        response.save_to_mmap(&path, body.len() as u64).unwrap(),
+        100_000
+    );
+    assert_eq!(std::fs::read(&path).unwrap(), body);
+
+    let response = minreq::get(chunked).send_lazy().unwrap();
+    assert_eq!(response.save_to_mmap(&path, 100).unwrap(), 5);
+    assert_eq!(std::fs::read(&path).unwrap(), b"hello");
+
+    let response = minreq::get(too_long).send_lazy().unwrap();
+    let result = response.save_to_mmap(&path, 5);
+    assert!(matches!(result, Err(minreq::Error::BodyOverflow)));
+
+    let response = minreq::head(head).send_lazy().unwrap();
+    assert_eq!(response.save_to_mmap(&path, 100).unwrap(), 0);
+    assert!(std::fs::read(&path).unwrap().is_empty());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_read_to_end_capped() {
+    let sized = || {
+        raw_server(|stream, _| {
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\n0123456789")
+                .unwrap();
+        })
+    };
+    let chunked = || {
+        raw_server(|stream, _| {
+            let response = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+                a\r\n0123456789\r\n0\r\n\r\n";
+            stream.write_all(response).unwrap();
+        })
+    };
+    for server in [sized, chunked].iter() {
+        let response = minreq::get(server()).send_lazy().unwrap();
+        assert_eq!(response.read_to_end_capped(10).unwrap(), b"0123456789");
+        let response = minreq::get(server()).send_lazy().unwrap();
+        let result = response.read_to_end_capped(9);
+        assert!(matches!(result, Err(minreq::Error::ResponseTooLarge)));
+    }
+}
+
 #[test]
 fn test_timeout_too_low() {
     setup();
@@ -102,6 +325,99 @@ fn test_redirect_post() {
     assert_eq!(body, "j: Q");
 }
 
+#[test]
+fn test_redirect_301_post_policy() {
+    use minreq::RedirectMethodPolicy;
+    setup();
+    // By default, the POST should be preserved, and the test server
+    // only accepts GET requests on the /a path.
+    let body = get_body(minreq::post(url("/redirect-301")).with_body("Q").send());
+    assert_eq!(body, "POST to /a is not valid.");
+
+    let body = get_body(
+        minreq::post(url("/redirect-301"))
+            .with_redirect_method_policy(RedirectMethodPolicy::PostToGet)
+            .with_body("Q")
+            .send(),
+    );
+    assert_eq!(body, "j: Q");
+}
+
+#[test]
+fn test_redirect_307_308_post() {
+    use minreq::RedirectMethodPolicy;
+    setup();
+    // The test server only accepts POST requests on the /echo path,
+    // and responds with the body it received.
+    for path in &["/redirect-307", "/redirect-308"] {
+        let response = minreq::post(url(path))
+            .with_redirect_method_policy(RedirectMethodPolicy::PostToGet)
+            .with_body("Q\0 body")
+            .send()
+            .unwrap();
+        assert_eq!(response.status_code, 200, "{}", path);
+        assert_eq!(response.redirect_count, 1);
+        assert_eq!(response.as_str().unwrap(), "Q\0 body");
+    }
+}
+
+#[test]
+fn test_redirect_strips_credentials() {
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let destination = raw_server(move |stream, head| {
+        sender.send(head.to_string()).unwrap();
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+            .unwrap();
+    });
+    let source = raw_server(move |stream, _| {
+        let response = format!(
+            "HTTP/1.1 302 Found\r\nLocation: {}/\r\nContent-Length: 0\r\n\r\n",
+            destination
+        );
+        stream.write_all(response.as_bytes()).unwrap();
+    });
+    let response = minreq::get(source.replace("127.0.0.1", "localhost"))
+        .with_header("Authorization", "Bearer secret")
+        .with_header("Cookie", "session=1")
+        .with_header("X-Other", "kept")
+        .send()
+        .unwrap();
+    assert_eq!(response.redirect_count, 1);
+
+    let head = receiver.recv().unwrap().to_lowercase();
+    assert!(!head.contains("authorization"));
+    assert!(!head.contains("cookie"));
+    assert!(head.contains("\r\nx-other: kept\r\n"));
+}
+
+#[test]
+fn test_same_origin_redirects() {
+    setup();
+    let response = minreq::get(url("/redirect"))
+        .with_body("Q")
+        .with_same_origin_redirects(true)
+        .send()
+        .unwrap();
+    assert_eq!(response.redirect_count, 1);
+    assert_eq!(response.as_str().unwrap(), "j: Q");
+
+    let destination = raw_server(|_, _| panic!("the redirection should not be followed"));
+    let source = raw_server(move |stream, _| {
+        let response = format!(
+            "HTTP/1.1 302 Found\r\nLocation: {}/\r\nContent-Length: 0\r\n\r\n",
+            destination
+        );
+        stream.write_all(response.as_bytes()).unwrap();
+    });
+    let response = minreq::get(source.replace("127.0.0.1", "localhost"))
+        .with_same_origin_redirects(true)
+        .send()
+        .unwrap();
+    assert_eq!(response.status_code, 302);
+    assert_eq!(response.redirect_count, 0);
+}
+
 #[test]
 fn test_redirect_with_fragment() {
     setup();
@@ -134,6 +450,289 @@ fn test_relative_redirect_get() {
     assert_eq!(body, "j: Q");
 }
 
+#[test]
+fn test_redirect_missing_location() {
+    setup();
+    let result = minreq::get(url("/redirect-no-location")).send();
+    assert!(matches!(
+        result,
+        Err(minreq::Error::RedirectLocationMissing)
+    ));
+
+    let response = minreq::get(url("/redirect-no-location"))
+        .with_lenient_redirects(true)
+        .send()
+        .unwrap();
+    assert_eq!(response.status_code, 302);
+    assert_eq!(response.as_str().unwrap(), "Nowhere to go.");
+}
+
+#[test]
+fn test_auto_auth() {
+    setup();
+    let response = minreq::get(url("/basic-auth")).send().unwrap();
+    assert_eq!(response.status_code, 401);
+    let challenges = response.auth_challenges();
+    assert!(challenges[0].is_scheme("Basic"));
+    assert_eq!(challenges[0].params["realm"], "test");
+
+    let response = minreq::get(url("/basic-auth"))
+        .with_auto_auth("user", "pass")
+        .send()
+        .unwrap();
+    assert_eq!(response.status_code, 200);
+    assert_eq!(response.as_str().unwrap(), "Authenticated.");
+
+    let response = minreq::get(url("/basic-auth"))
+        .with_auto_auth("user", "wrong")
+        .send()
+        .unwrap();
+    assert_eq!(response.status_code, 401);
+}
+
+#[test]
+fn test_auto_auth_not_sent_to_other_origins() {
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let destination = raw_server(move |stream, head| {
+        sender.send(head.to_string()).unwrap();
+        let response = b"HTTP/1.1 401 Unauthorized\r\n\
+            WWW-Authenticate: Basic realm=\"elsewhere\"\r\n\
+            Content-Length: 0\r\n\r\n";
+        stream.write_all(response).unwrap();
+    });
+    let source = raw_server(move |stream, _| {
+        let response = format!(
+            "HTTP/1.1 302 Found\r\nLocation: {}/\r\nContent-Length: 0\r\n\r\n",
+            destination
+        );
+        stream.write_all(response.as_bytes()).unwrap();
+    });
+    let response = minreq::get(source.replace("127.0.0.1", "localhost"))
+        .with_auto_auth("user", "pass")
+        .with_timeout(5)
+        .send()
+        .unwrap();
+    assert_eq!(response.status_code, 401);
+    assert_eq!(response.redirect_count, 1);
+
+    let head = receiver.recv().unwrap().to_lowercase();
+    assert!(!head.contains("authorization"));
+}
+
+#[test]
+fn test_version() {
+    let version = minreq::version();
+    let numbers = version.split('-').next().unwrap().split('.');
+    assert_eq!(
+        numbers.clone().count(),
+        3,
+        "not major.minor.patch: {}",
+        version
+    );
+    for number in numbers {
+        number.parse::<u32>().unwrap();
+    }
+}
+
+#[test]
+fn test_tls_backend() {
+    let https_enabled = cfg!(any(
+        feature = "rustls",
+        feature = "openssl",
+        feature = "native-tls"
+    ));
+    assert_eq!(minreq::https_available(), https_enabled);
+    if cfg!(feature = "rustls") {
+        assert_eq!(minreq::tls_backend(), Some("rustls"));
+    } else if !https_enabled {
+        assert_eq!(minreq::tls_backend(), None);
+    }
+}
+
+#[test]
+fn test_wire_logger() {
+    setup();
+    let log = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let logger_log = log.clone();
+    let response = minreq::get(url("/a"))
+        .with_wire_logger(move |direction, bytes| {
+            logger_log.lock().unwrap().push((direction, bytes.to_vec()));
+        })
+        .send()
+        .unwrap();
+    assert_eq!(response.as_str().unwrap(), "j: ");
+
+    let log = log.lock().unwrap();
+    let collect = |direction| {
+        let bytes = log.iter().filter(|(d, _)| *d == direction);
+        let bytes: Vec<u8> = bytes.flat_map(|(_, bytes)| bytes.clone()).collect();
+        String::from_utf8(bytes).unwrap()
+    };
+    let sent = collect(minreq::Direction::Sent);
+    let received = collect(minreq::Direction::Received);
+    assert!(sent.starts_with("GET /a HTTP/1.1\r\n"));
+    assert!(sent.ends_with("\r\n\r\n"));
+    assert!(received.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(received.ends_with("\r\n\r\nj: "));
+}
+
+#[test]
+fn test_large_body_write() {
+    setup();
+    let body = "minreq ".repeat(20_000);
+    let sent = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let logger_sent = sent.clone();
+    let response = minreq::post(url("/echo"))
+        .with_body(body.clone())
+        .with_wire_logger(move |direction, bytes| {
+            if direction == minreq::Direction::Sent {
+                logger_sent.lock().unwrap().push(bytes.to_vec());
+            }
+        })
+        .send()
+        .unwrap();
+    assert_eq!(response.as_str().unwrap(), body);
+
+    // The head and the body are written separately.
+    let sent = sent.lock().unwrap();
+    assert_eq!(sent.len(), 2);
+    assert!(sent[0].ends_with(b"\r\n\r\n"));
+    assert_eq!(sent[1], body.as_bytes());
+}
+
+#[test]
+fn test_body_from_reader() {
+    setup();
+    let body = "minreq ".repeat(5_000);
+    let response = minreq::post(url("/echo"))
+        .with_body_from_reader(io::Cursor::new(body.clone()), Some(body.len() as u64))
+        .send()
+        .unwrap();
+    assert_eq!(response.as_str().unwrap(), body);
+
+    let sent = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let logger_sent = sent.clone();
+    let response = minreq::post(url("/echo"))
+        .with_body_from_reader(io::Cursor::new(body.clone()), None)
+        .with_wire_logger(move |direction, bytes| {
+            if direction == minreq::Direction::Sent {
+                logger_sent.lock().unwrap().extend_from_slice(bytes);
+            }
+        })
+        .send()
+        .unwrap();
+    assert_eq!(response.as_str().unwrap(), body);
+    let sent = String::from_utf8(sent.lock().unwrap().clone()).unwrap();
+    assert!(sent.contains("Transfer-Encoding: chunked\r\n"));
+    assert!(!sent.contains("Content-Length"));
+    assert!(sent.ends_with("\r\n0\r\n\r\n"));
+
+    // The reader ends before the given length.
+    let result = minreq::post(url("/echo"))
+        .with_body_from_reader(io::Cursor::new("short"), Some(100))
+        .send();
+    assert!(
+        matches!(result, Err(minreq::Error::IoError(ref err)) if err.kind() == io::ErrorKind::UnexpectedEof)
+    );
+
+    struct FailingReader;
+    impl Read for FailingReader {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::new(io::ErrorKind::Other, "disk on fire"))
+        }
+    }
+    let request = minreq::post(url("/echo")).with_body_from_reader(FailingReader, None);
+    let result = request.clone().send();
+    assert!(
+        matches!(result, Err(minreq::Error::IoError(ref err)) if err.to_string() == "disk on fire")
+    );
+    // The reader was used up by the first send.
+    assert!(matches!(request.send(), Err(minreq::Error::IoError(_))));
+}
+
+#[test]
+fn test_socket_config() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    setup();
+    let connections = Arc::new(AtomicUsize::new(0));
+    let config_connections = connections.clone();
+    let response = minreq::get(url("/relativeredirect"))
+        .with_socket_config(move |tcp| {
+            tcp.set_nodelay(true).unwrap();
+            config_connections.fetch_add(1, Ordering::SeqCst);
+        })
+        .send()
+        .unwrap();
+    assert_eq!(response.status_code, 200);
+    // One connection for the original request, one for the redirection.
+    assert_eq!(connections.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn test_ip_family() {
+    setup();
+    let response = minreq::get(url("/a"))
+        .with_ip_family(minreq::IpFamily::V4Only)
+        .send()
+        .unwrap();
+    assert_eq!(response.as_str().unwrap(), "j: ");
+
+    let result = minreq::get("http://127.0.0.1:35562/a")
+        .with_ip_family(minreq::IpFamily::V6Only)
+        .send();
+    assert!(matches!(result, Err(minreq::Error::AddressNotFound)));
+}
+
+#[test]
+fn test_redirect_count() {
+    setup();
+    let response = minreq::get(url("/relativeredirect")).send().unwrap();
+    assert_eq!(response.redirect_count, 1);
+    let response = minreq::get(url("/a")).send().unwrap();
+    assert_eq!(response.redirect_count, 0);
+}
+
+#[test]
+fn test_fetch() {
+    setup();
+    assert_eq!(minreq::fetch_text(url("/a")).unwrap(), "j: ");
+    assert_eq!(minreq::fetch_bytes(url("/a")).unwrap(), b"j: ");
+    let result = minreq::fetch_text(url("/not_found"));
+    assert!(matches!(
+        result,
+        Err(minreq::Error::StatusCode { code: 404, .. })
+    ));
+}
+
+#[test]
+fn test_error_for_status() {
+    setup();
+    let response = minreq::get(url("/a")).send().unwrap();
+    assert_eq!(
+        response.error_for_status().unwrap().as_str().unwrap(),
+        "j: "
+    );
+
+    let response = minreq::get(url("/not_found")).send().unwrap();
+    match response.error_for_status_ref() {
+        Err(minreq::Error::StatusCode {
+            code,
+            url: error_url,
+        }) => {
+            assert_eq!(code, 404);
+            assert_eq!(error_url, url("/not_found"));
+        }
+        result => panic!("expected a StatusCode error, got {:?}", result),
+    }
+    assert_eq!(response.as_str().unwrap(), "Not Found");
+    assert!(matches!(
+        response.error_for_status(),
+        Err(minreq::Error::StatusCode { code: 404, .. })
+    ));
+}
+
 #[test]
 fn test_head() {
     setup();
@@ -193,12 +792,21 @@ fn tcp_connect_timeout() {
     let resp = minreq::Request::new(minreq::Method::Get, "http://127.0.0.1:32162")
         .with_timeout(1)
         .send();
-    assert!(resp.is_err());
-    if let Some(minreq::Error::IoError(err)) = resp.err() {
-        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
-    } else {
-        panic!("timeout test request did not return an error");
-    }
+    assert!(matches!(resp, Err(minreq::Error::Timeout)), "{:?}", resp);
+}
+
+#[test]
+fn test_connection_refused() {
+    let port = {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().port()
+    };
+    let result = minreq::get(format!("http://127.0.0.1:{}", port)).send();
+    assert!(
+        matches!(result, Err(minreq::Error::ConnectionRefused)),
+        "{:?}",
+        result
+    );
 }
 
 #[test]
@@ -246,3 +854,961 @@ fn test_massive_content_length() {
     std::thread::sleep(std::time::Duration::from_millis(500));
     // If it were to crash, it would have at this point. Pass!
 }
+
+#[test]
+fn test_connection_closed_during_headers() {
+    let responses: [&[u8]; 4] = [
+        b"",
+        b"HTTP/1.1 2",
+        b"HTTP/1.1 200 OK\r\nContent-Le",
+        b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n",
+    ];
+    for &response in responses.iter() {
+        let url = raw_server(move |stream, _| stream.write_all(response).unwrap());
+        let result = minreq::get(url).send();
+        assert!(
+            matches!(result, Err(minreq::Error::ConnectionClosedDuringHeaders)),
+            "{:?}: {:?}",
+            String::from_utf8_lossy(response),
+            result
+        );
+    }
+}
+
+#[test]
+fn test_malformed_status_line() {
+    let url = raw_server(|stream, _| {
+        stream.write_all(b"garbage\r\n\r\n").unwrap();
+    });
+    let result = minreq::get(url).send();
+    assert!(matches!(result, Err(minreq::Error::MalformedStatusLine)));
+}
+
+#[test]
+fn test_headers_split_across_reads() {
+    let url = raw_server(|stream, _| {
+        let parts: [&[u8]; 8] = [
+            b"HTTP/1.1 20",
+            b"0 OK\r",
+            b"\nContent-Length: 5\r\n",
+            b"X-Split: first ",
+            b"second\r\nX-Ot",
+            b"her: value\r\n",
+            b"\r",
+            b"\nhello",
+        ];
+        for part in parts.iter() {
+            stream.write_all(part).unwrap();
+            stream.flush().unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+    });
+    let response = minreq::get(url).send().unwrap();
+    assert_eq!(response.status_code, 200);
+    assert_eq!(response.headers["x-split"], "first second");
+    assert_eq!(response.headers["x-other"], "value");
+    assert_eq!(response.as_str().unwrap(), "hello");
+}
+
+#[test]
+fn test_into_stream() {
+    let url = raw_server(|stream, _| {
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nfirst")
+            .unwrap();
+        let mut head = Vec::new();
+        let mut byte = [0];
+        while !head.ends_with(b"\r\n\r\n") {
+            stream.read_exact(&mut byte).unwrap();
+            head.push(byte[0]);
+        }
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\nConnection: close\r\n\r\nsecond")
+            .unwrap();
+    });
+
+    let mut response = minreq::get(url).send_lazy().unwrap();
+    let body = (&mut response).map(|r| r.unwrap().0).collect::<Vec<u8>>();
+    assert_eq!(body, b"first");
+    let mut stream = response
+        .into_stream()
+        .expect("connection should be reusable");
+    stream.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+    let mut second = String::new();
+    stream.read_to_string(&mut second).unwrap();
+    assert!(second.ends_with("\r\n\r\nsecond"));
+
+    let url = raw_server(|stream, _| {
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nfirst")
+            .unwrap();
+    });
+    let response = minreq::get(url).send_lazy().unwrap();
+    // The body hasn't been read, so the connection can't be reused.
+    assert!(response.into_stream().is_none());
+}
+
+#[test]
+fn test_timings() {
+    let url = raw_server(|stream, _| {
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+            .unwrap();
+    });
+    let timings = minreq::get(url).send().unwrap().timings();
+    assert_eq!(timings.tls_handshake, None);
+    assert!(timings.first_byte >= std::time::Duration::from_millis(100));
+}
+
+#[test]
+fn test_lazy_expected_lengths() {
+    let lengths = |response: &'static [u8]| {
+        let url = raw_server(move |stream, _| stream.write_all(response).unwrap());
+        let response = minreq::get(url).send_lazy().unwrap();
+        response
+            .map(|result| result.unwrap().1)
+            .collect::<Vec<usize>>()
+    };
+
+    let content_length = b"HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\r\nabc";
+    assert_eq!(lengths(content_length), vec![3, 2, 1]);
+
+    let chunked = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+        3\r\nabc\r\n2\r\nde\r\n0\r\n\r\n";
+    assert_eq!(lengths(chunked), vec![3, 2, 1, 2, 1]);
+
+    let close_delimited = b"HTTP/1.1 200 OK\r\nConnection: close\r\n\r\nabc";
+    assert_eq!(lengths(close_delimited), vec![1, 1, 1]);
+}
+
+#[test]
+fn test_lazy_peek() {
+    let url = raw_server(|stream, _| {
+        let response = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+            2\r\n\x1f\x8b\r\n3\r\nabc\r\n0\r\n\r\n";
+        stream.write_all(response).unwrap();
+    });
+    let mut response = minreq::get(url).send_lazy().unwrap();
+    assert_eq!(response.peek(1).unwrap(), b"\x1f");
+    assert_eq!(response.peek(3).unwrap(), b"\x1f\x8ba");
+    assert_eq!(response.next().unwrap().unwrap(), (0x1f, 2));
+    assert_eq!(response.peek(10).unwrap(), b"\x8babc");
+    let mut body = Vec::new();
+    response.read_to_end(&mut body).unwrap();
+    assert_eq!(body, b"\x8babc");
+    assert_eq!(response.peek(1).unwrap(), b"");
+}
+
+#[test]
+fn test_strict_header_parsing() {
+    let bare_cr_server = || {
+        raw_server(|stream, _| {
+            let response = b"HTTP/1.1 200 OK\r\nX-Smuggled: a\rb\r\nContent-Length: 0\r\n\r\n";
+            stream.write_all(response).unwrap();
+        })
+    };
+
+    let response = minreq::get(bare_cr_server()).send().unwrap();
+    assert_eq!(response.headers["x-smuggled"], "a\rb");
+
+    let result = minreq::get(bare_cr_server())
+        .with_strict_parsing(true)
+        .send();
+    assert!(matches!(result, Err(minreq::Error::MalformedHeader)));
+}
+
+#[test]
+fn test_strict_framing() {
+    use minreq::Error::*;
+    type Case = (bool, &'static [u8], fn(&minreq::Error) -> bool);
+    let cases: [Case; 5] = [
+        (
+            false,
+            b"HTTP/1.1 200 OK\r\nContent-Length: 2\nX-A: b\r\n\r\nhi",
+            |err| matches!(err, MalformedHeader),
+        ),
+        (
+            false,
+            b"HTTP/1.1 200 OK\r\nX-A: b\r\n  c\r\nContent-Length: 2\r\n\r\nhi",
+            |err| matches!(err, MalformedHeader),
+        ),
+        (
+            false,
+            b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\nTransfer-Encoding: chunked\r\n\r\n2\r\nhi\r\n0\r\n\r\n",
+            |err| matches!(err, ConflictingBodyLength),
+        ),
+        (
+            false,
+            b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nContent-Length: 3\r\n\r\nhi",
+            |err| matches!(err, ConflictingBodyLength),
+        ),
+        (
+            true,
+            b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nhi",
+            |err| matches!(err, UnexpectedBody),
+        ),
+    ];
+
+    for &(is_head, response, is_expected_error) in cases.iter() {
+        let server = || raw_server(move |stream, _| stream.write_all(response).unwrap());
+        let request = |url| {
+            if is_head {
+                minreq::head(url)
+            } else {
+                minreq::get(url)
+            }
+        };
+
+        let lenient = request(server()).send().unwrap();
+        assert_eq!(lenient.as_str().unwrap(), if is_head { "" } else { "hi" });
+
+        let strict = request(server()).with_strict_parsing(true).send();
+        assert!(is_expected_error(&strict.unwrap_err()));
+    }
+}
+
+#[test]
+fn test_strict_ambiguous_body_length() {
+    let keep_alive_server = || {
+        raw_server(|stream, _| {
+            let response = b"HTTP/1.1 200 OK\r\nConnection: keep-alive\r\n\r\nbody";
+            stream.write_all(response).unwrap();
+            // Keep the connection open, like a keep-alive connection would be.
+            std::thread::sleep(std::time::Duration::from_secs(2));
+        })
+    };
+
+    let result = minreq::get(keep_alive_server()).with_timeout(1).send();
+    assert!(matches!(result, Err(minreq::Error::IoError(_))));
+
+    let result = minreq::get(keep_alive_server())
+        .with_timeout(1)
+        .with_strict_parsing(true)
+        .send();
+    assert!(matches!(result, Err(minreq::Error::AmbiguousBodyLength)));
+}
+
+#[test]
+fn test_header_raw() {
+    let url = raw_server(|stream, _| {
+        let response = b"HTTP/1.1 200 OK\r\nX-Signature:  abc= \r\nContent-Length: 0\r\n\r\n";
+        stream.write_all(response).unwrap();
+    });
+    let response = minreq::get(url).send().unwrap();
+    assert_eq!(response.headers["x-signature"], " abc= ");
+    assert_eq!(response.header_raw("x-signature"), Some("  abc= "));
+    assert_eq!(response.header_raw("X-SIGNATURE"), Some("  abc= "));
+    assert_eq!(response.header_raw("x-missing"), None);
+}
+
+/// Starts a server which responds with `body` and the given
+/// `Content-Encoding`, and sends the request head it received into
+/// the returned channel.
+#[cfg(feature = "decompress")]
+fn compressed_server(
+    content_encoding: &'static str,
+    body: Vec<u8>,
+    chunked: bool,
+) -> (String, std::sync::mpsc::Receiver<String>) {
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let url = raw_server(move |stream, head| {
+        sender.send(head.to_string()).ok();
+        let framing = if chunked {
+            "Transfer-Encoding: chunked".to_string()
+        } else {
+            format!("Content-Length: {}", body.len())
+        };
+        let head = format!(
+            "HTTP/1.1 200 OK\r\nContent-Encoding: {}\r\n{}\r\n\r\n",
+            content_encoding, framing
+        );
+        stream.write_all(head.as_bytes()).unwrap();
+        if chunked {
+            let (first, second) = body.split_at(body.len() / 2);
+            for chunk in &[first, second] {
+                write!(stream, "{:x}\r\n", chunk.len()).unwrap();
+                stream.write_all(chunk).unwrap();
+                stream.write_all(b"\r\n").unwrap();
+            }
+            stream.write_all(b"0\r\n\r\n").unwrap();
+        } else {
+            stream.write_all(&body).unwrap();
+        }
+    });
+    (url, receiver)
+}
+
+/// `"Hello, world! "` repeated 100 times, compressed with gzip.
+#[cfg(feature = "decompress")]
+const HELLO_GZIP: &[u8] = &[
+    0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0xf3, 0x48, 0xcd, 0xc9, 0xc9, 0xd7,
+    0x51, 0x28, 0xcf, 0x2f, 0xca, 0x49, 0x51, 0x54, 0xf0, 0x18, 0xe5, 0x8d, 0xf2, 0x46, 0x79, 0xa3,
+    0xbc, 0x51, 0x1e, 0x8d, 0x79, 0x00, 0x29, 0xec, 0x57, 0xcb, 0x78, 0x05, 0x00, 0x00,
+];
+
+/// `"Hello, world! "` repeated 100 times, compressed with zlib.
+#[cfg(feature = "decompress")]
+const HELLO_ZLIB: &[u8] = &[
+    0x78, 0x9c, 0xf3, 0x48, 0xcd, 0xc9, 0xc9, 0xd7, 0x51, 0x28, 0xcf, 0x2f, 0xca, 0x49, 0x51, 0x54,
+    0xf0, 0x18, 0xe5, 0x8d, 0xf2, 0x46, 0x79, 0xa3, 0xbc, 0x51, 0x1e, 0x8d, 0x79, 0x00, 0x46, 0xb8,
+    0xd2, 0x14,
+];
+
+/// `"Hello, "` and `"world!"` compressed as two separate gzip members.
+#[cfg(feature = "decompress")]
+const HELLO_GZIP_MEMBERS: &[u8] = &[
+    0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0xf3, 0x48, 0xcd, 0xc9, 0xc9, 0xd7,
+    0x51, 0x00, 0x00, 0x05, 0x6f, 0x57, 0xde, 0x07, 0x00, 0x00, 0x00, 0x1f, 0x8b, 0x08, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x03, 0x2b, 0xcf, 0x2f, 0xca, 0x49, 0x51, 0x04, 0x00, 0xe8, 0x98, 0x84,
+    0x71, 0x06, 0x00, 0x00, 0x00,
+];
+
+#[test]
+#[cfg(feature = "decompress")]
+fn test_decompress() {
+    let text = "Hello, world! ".repeat(100);
+    let gzip = HELLO_GZIP.to_vec();
+    let zlib = HELLO_ZLIB.to_vec();
+
+    let (url, head) = compressed_server("gzip", gzip.clone(), false);
+    let response = minreq::get(url).send().unwrap();
+    assert!(head
+        .recv()
+        .unwrap()
+        .contains("\r\nAccept-Encoding: gzip, deflate\r\n"));
+    assert_eq!(response.as_str().unwrap(), text);
+    assert_eq!(response.headers["content-encoding"], "gzip");
+    assert_eq!(response.headers["content-length"], gzip.len().to_string());
+
+    let (url, _) = compressed_server("gzip", gzip, true);
+    let response = minreq::get(url).send().unwrap();
+    assert_eq!(response.as_str().unwrap(), text);
+
+    let (url, head) = compressed_server("deflate", zlib, false);
+    let request = minreq::get(url).with_header("accept-encoding", "deflate");
+    let response = request.send().unwrap();
+    let head = head.recv().unwrap();
+    assert!(head.contains("\r\naccept-encoding: deflate\r\n"));
+    assert!(!head.contains("gzip"));
+    assert_eq!(response.as_str().unwrap(), text);
+}
+
+#[test]
+#[cfg(feature = "decompress")]
+fn test_decompress_edge_cases() {
+    let (url, _) = compressed_server("gzip", Vec::new(), false);
+    let response = minreq::get(url).send().unwrap();
+    assert!(response.as_bytes().is_empty());
+
+    let gzip = HELLO_GZIP[..HELLO_GZIP.len() - 4].to_vec();
+    let (url, _) = compressed_server("gzip", gzip, false);
+    let response = minreq::get(url).send();
+    assert!(matches!(response, Err(minreq::Error::IoError(_))));
+
+    let zlib = HELLO_ZLIB[..HELLO_ZLIB.len() / 2].to_vec();
+    let (url, _) = compressed_server("deflate", zlib, false);
+    let response = minreq::get(url).send();
+    assert!(matches!(response, Err(minreq::Error::IoError(_))));
+
+    let (url, _) = compressed_server("gzip", HELLO_GZIP_MEMBERS.to_vec(), false);
+    let response = minreq::get(url).send().unwrap();
+    assert_eq!(response.as_str().unwrap(), "Hello, world!");
+
+    let (url, head) = compressed_server("gzip", HELLO_GZIP.to_vec(), false);
+    let request = minreq::get(url).without_auto_decompression();
+    let response = request.send().unwrap();
+    assert!(!head.recv().unwrap().contains("Accept-Encoding"));
+    assert_eq!(response.as_bytes(), HELLO_GZIP);
+
+    let (url, _) = compressed_server("br", b"not brotli".to_vec(), false);
+    let response = minreq::get(url).send().unwrap();
+    assert_eq!(response.as_bytes(), b"not brotli");
+}
+
+#[test]
+fn test_cookies() {
+    let url = raw_server(|stream, _| {
+        let response = b"HTTP/1.1 200 OK\r\n\
+            Set-Cookie: a=1; Path=/; HttpOnly\r\n\
+            Content-Length: 0\r\n\
+            set-cookie: b=2; Secure\r\n\r\n";
+        stream.write_all(response).unwrap();
+    });
+    let response = minreq::get(url).send().unwrap();
+    let cookies = response.cookies();
+    assert_eq!(cookies.len(), 2);
+    assert_eq!(
+        (cookies[0].name.as_str(), cookies[0].value.as_str()),
+        ("a", "1")
+    );
+    assert!(cookies[0].http_only);
+    assert_eq!(
+        (cookies[1].name.as_str(), cookies[1].value.as_str()),
+        ("b", "2")
+    );
+    assert!(cookies[1].secure);
+}
+
+#[test]
+fn test_headers_all() {
+    setup();
+    let response = minreq::get(url("/multi_cookie")).send().unwrap();
+    assert_eq!(response.headers_all("set-cookie"), vec!["a=1", "b=2"]);
+    assert_eq!(response.headers["set-cookie"], "b=2");
+    assert!(response.headers_all("x-missing").is_empty());
+}
+
+#[test]
+fn test_body_capacity_hint() {
+    let close_delimited_server = || {
+        raw_server(|stream, _| {
+            stream.write_all(b"HTTP/1.1 200 OK\r\n\r\n").unwrap();
+            stream.write_all(&[b'.'; 1000]).unwrap();
+        })
+    };
+
+    // The body fits in the space allocated for the hint, so the
+    // buffer is never grown while reading it.
+    let body = minreq::get(close_delimited_server())
+        .with_body_capacity_hint(4096)
+        .send()
+        .unwrap()
+        .into_bytes();
+    assert_eq!(body.len(), 1000);
+    assert_eq!(body.capacity(), 4096);
+
+    // Without the hint, the buffer starts empty and grows as needed.
+    let body = minreq::get(close_delimited_server())
+        .send()
+        .unwrap()
+        .into_bytes();
+    assert_eq!(body.len(), 1000);
+    assert!(body.capacity() < 4096);
+}
+
+#[test]
+fn test_close_delimited_body_cap() {
+    let close_delimited_server = || {
+        raw_server(|stream, _| {
+            stream.write_all(b"HTTP/1.1 200 OK\r\n\r\n").unwrap();
+            stream.write_all(&[b'.'; 100]).ok();
+        })
+    };
+
+    let result = minreq::get(close_delimited_server())
+        .with_max_response_size(99)
+        .send();
+    assert!(matches!(result, Err(minreq::Error::BodyOverflow)));
+
+    let response = minreq::get(close_delimited_server())
+        .with_max_response_size(100)
+        .send()
+        .unwrap();
+    assert_eq!(response.as_bytes().len(), 100);
+
+    // The capacity hint should be capped by the max size, allocating
+    // usize::MAX bytes would panic.
+    let response = minreq::get(close_delimited_server())
+        .with_max_response_size(100)
+        .with_body_capacity_hint(usize::MAX)
+        .send()
+        .unwrap();
+    assert_eq!(response.as_bytes().len(), 100);
+}
+
+#[test]
+fn test_lazy_body_cap() {
+    let server = || {
+        raw_server(|stream, _| {
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 100\r\n\r\n")
+                .unwrap();
+            stream.write_all(&[b'.'; 100]).ok();
+        })
+    };
+
+    let mut response = minreq::get(server())
+        .with_max_response_size(99)
+        .send_lazy()
+        .unwrap();
+    let mut body = Vec::new();
+    let err = response.read_to_end(&mut body).unwrap_err();
+    let err = err
+        .into_inner()
+        .unwrap()
+        .downcast::<minreq::Error>()
+        .unwrap();
+    assert!(matches!(*err, minreq::Error::BodyOverflow));
+    // The error sticks, instead of the body just ending.
+    assert!(matches!(
+        response.next(),
+        Some(Err(minreq::Error::BodyOverflow))
+    ));
+
+    let mut response = minreq::get(server())
+        .with_max_response_size(100)
+        .send_lazy()
+        .unwrap();
+    let mut body = Vec::new();
+    response.read_to_end(&mut body).unwrap();
+    assert_eq!(body.len(), 100);
+}
+
+#[test]
+#[cfg(feature = "proxy")]
+fn test_forward_proxy() {
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let proxy_url = raw_server(move |stream, head| {
+        sender.send(head.to_string()).unwrap();
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 8\r\n\r\nproxied!")
+            .unwrap();
+    });
+    let proxy = minreq::Proxy::new(proxy_url.replace("http://", "http://user:pass@")).unwrap();
+    let response = minreq::get("http://example.com/a?b=c#d")
+        .with_proxy(proxy)
+        .send()
+        .unwrap();
+    assert_eq!(response.as_str().unwrap(), "proxied!");
+    // Plain HTTP requests aren't tunneled.
+    assert_eq!(response.proxy_connect_status(), None);
+
+    let head = receiver.recv().unwrap();
+    assert!(head.starts_with("GET http://example.com/a?b=c HTTP/1.1\r\n"));
+    assert!(head.contains("\r\nHost: example.com\r\n"));
+    assert!(head.contains("\r\nProxy-Authorization: Basic dXNlcjpwYXNz\r\n"));
+}
+
+#[test]
+fn test_resolver() {
+    setup();
+    let response = minreq::get("http://service.internal:35562/a")
+        .with_resolver(|host, port| match host {
+            "service.internal" => Ok(vec![std::net::SocketAddr::from(([127, 0, 0, 1], port))]),
+            _ => Err(io::Error::new(io::ErrorKind::NotFound, "unknown host")),
+        })
+        .send();
+    assert_eq!(get_body(response), "j: ");
+
+    let result = minreq::get("http://other.internal/")
+        .with_resolver(|_, _| Err(io::Error::new(io::ErrorKind::NotFound, "unknown host")))
+        .send();
+    assert!(
+        matches!(result, Err(minreq::Error::DnsFailure(ref err)) if err.kind() == io::ErrorKind::NotFound)
+    );
+
+    let result = minreq::get("http://other.internal/")
+        .with_resolver(|_, _| Ok(Vec::new()))
+        .send();
+    assert!(matches!(result, Err(minreq::Error::AddressNotFound)));
+}
+
+#[test]
+#[cfg(feature = "proxy")]
+fn test_socks5_proxy() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let (sender, receiver) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut greeting = [0; 4];
+        stream.read_exact(&mut greeting).unwrap();
+        assert_eq!(greeting, [5, 2, 0, 2]);
+        stream.write_all(&[5, 2]).unwrap();
+        let mut auth = [0; 9];
+        stream.read_exact(&mut auth).unwrap();
+        assert_eq!(&auth, b"\x01\x04user\x02pw");
+        stream.write_all(&[1, 0]).unwrap();
+        let mut connect = [0; 20];
+        stream.read_exact(&mut connect).unwrap();
+        stream.write_all(&[5, 0, 0, 1, 127, 0, 0, 1, 0, 0]).unwrap();
+        sender.send(connect.to_vec()).unwrap();
+
+        // Act as the other end of the tunnel.
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            let mut byte = [0];
+            stream.read_exact(&mut byte).unwrap();
+            head.push(byte[0]);
+        }
+        sender.send(head).unwrap();
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nsocks")
+            .unwrap();
+    });
+
+    let proxy = minreq::Proxy::new(format!("socks5h://user:pw@127.0.0.1:{}", port)).unwrap();
+    let response = minreq::get("http://socks.example/path")
+        .with_proxy(proxy)
+        .send()
+        .unwrap();
+    assert_eq!(response.as_str().unwrap(), "socks");
+    assert_eq!(response.proxy_connect_status(), None);
+
+    let connect = receiver.recv().unwrap();
+    assert_eq!(&connect[..5], &[5, 1, 0, 3, 13]);
+    assert_eq!(&connect[5..18], b"socks.example");
+    assert_eq!(&connect[18..20], &[0, 80]);
+    let head = String::from_utf8(receiver.recv().unwrap()).unwrap();
+    assert!(head.starts_with("GET /path HTTP/1.1\r\n"), "{}", head);
+    assert!(!head.contains("Proxy-Authorization"));
+}
+
+#[test]
+#[cfg(feature = "proxy")]
+fn test_connect_tunnel() {
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let proxy_url = raw_server(move |stream, head| {
+        sender.send(head.to_string()).unwrap();
+        stream
+            .write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n")
+            .unwrap();
+        // Act as the other end of the tunnel.
+        let mut ping = [0; 4];
+        stream.read_exact(&mut ping).unwrap();
+        assert_eq!(&ping, b"ping");
+        stream.write_all(b"pong").unwrap();
+    });
+    let proxy = minreq::Proxy::new(proxy_url).unwrap();
+    let mut tunnel = minreq::get("http://tunnel.example:5000")
+        .with_proxy(proxy)
+        .connect_tunnel()
+        .unwrap();
+    tunnel.write_all(b"ping").unwrap();
+    let mut pong = [0; 4];
+    tunnel.read_exact(&mut pong).unwrap();
+    assert_eq!(&pong, b"pong");
+
+    let head = receiver.recv().unwrap();
+    assert!(head.starts_with("CONNECT tunnel.example:5000 HTTP/1.1\r\n"));
+
+    let result = minreq::get("http://tunnel.example:5000")
+        .without_env_proxy()
+        .connect_tunnel();
+    assert!(matches!(result, Err(minreq::Error::NoProxy)));
+}
+
+#[test]
+#[cfg(all(feature = "proxy", feature = "rustls"))]
+fn test_proxy_connect_status() {
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let proxy_url = raw_server(move |stream, connect| {
+        sender.send(connect.to_string()).unwrap();
+        stream.write_all(b"HTTP/1.1 201 Created\r\n\r\n").unwrap();
+        let response = b"HTTP/1.1 200 OK\r\nContent-Length: 9\r\n\r\ntunneled!";
+        tls_respond(stream, response);
+    });
+    let response = minreq::get("https://localhost/")
+        .with_proxy(minreq::Proxy::new(proxy_url).unwrap())
+        .with_rustls_config(tls_client_config())
+        .send()
+        .unwrap();
+    assert_eq!(response.as_str().unwrap(), "tunneled!");
+    assert_eq!(response.proxy_connect_status(), Some(201));
+
+    let connect = receiver.recv().unwrap();
+    assert!(connect.starts_with("CONNECT localhost:443 HTTP/1.1\r\n"));
+}
+
+#[test]
+#[cfg(all(
+    feature = "proxy",
+    any(feature = "rustls", feature = "openssl", feature = "native-tls")
+))]
+fn test_proxy_connect_headers() {
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let proxy_url = raw_server(move |stream, head| {
+        sender.send(head.to_string()).unwrap();
+        stream
+            .write_all(b"HTTP/1.1 407 Proxy Authentication Required\r\n\r\n")
+            .unwrap();
+    });
+    let proxy = minreq::Proxy::new(proxy_url).unwrap();
+    let result = minreq::get("https://example.com/a")
+        .with_proxy(proxy)
+        .send();
+    assert!(matches!(result, Err(minreq::Error::InvalidProxyCreds)));
+
+    let head = receiver.recv().unwrap();
+    assert!(head.starts_with("CONNECT example.com:443 HTTP/1.1\r\n"));
+    assert!(head.contains("\r\nHost: example.com:443\r\n"));
+    assert!(head.contains("\r\nProxy-Connection: keep-alive\r\n"));
+}
+
+#[test]
+#[cfg(all(
+    feature = "proxy",
+    any(feature = "rustls", feature = "openssl", feature = "native-tls")
+))]
+fn test_proxy_connect_timeout() {
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let proxy_url = raw_server(move |stream, _| {
+        // Never respond to the CONNECT, just wait for the client to
+        // give up and close the connection.
+        let timeout = std::time::Duration::from_secs(5);
+        stream.set_read_timeout(Some(timeout)).unwrap();
+        let closed = matches!(stream.read(&mut [0]), Ok(0));
+        sender.send(closed).unwrap();
+    });
+    let proxy = minreq::Proxy::new(proxy_url).unwrap();
+    let result = minreq::get("https://example.com")
+        .with_proxy(proxy)
+        .with_timeout(1)
+        .send();
+    assert!(
+        matches!(result, Err(minreq::Error::Timeout)),
+        "{:?}",
+        result
+    );
+    assert!(
+        receiver.recv().unwrap(),
+        "the connection to the proxy was left open"
+    );
+}
+
+#[test]
+fn test_headers_timeout() {
+    use std::thread::sleep;
+    use std::time::Duration;
+
+    let slow_head = raw_server(|stream, _| {
+        sleep(Duration::from_millis(500));
+        let _ = stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok");
+    });
+    let result = minreq::get(slow_head)
+        .with_headers_timeout(Duration::from_millis(100))
+        .send();
+    assert!(
+        matches!(result, Err(minreq::Error::Timeout)),
+        "{:?}",
+        result
+    );
+
+    // Only the head needs to arrive in time, the body may take longer.
+    let slow_body = raw_server(|stream, _| {
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\n")
+            .unwrap();
+        sleep(Duration::from_millis(500));
+        stream.write_all(b"ok").unwrap();
+    });
+    let response = minreq::get(slow_body)
+        .with_headers_timeout(Duration::from_millis(100))
+        .send()
+        .unwrap();
+    assert_eq!(response.as_str().unwrap(), "ok");
+}
+
+#[test]
+fn test_max_trailer_size() {
+    let server = || {
+        raw_server(|stream, _| {
+            let trailer = "X-Trailer: ".to_string() + &"t".repeat(200) + "\r\n";
+            let response = "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+                            2\r\nhi\r\n0\r\n"
+                .to_string()
+                + &trailer
+                + "\r\n";
+            stream.write_all(response.as_bytes()).unwrap();
+        })
+    };
+
+    // The trailer fits in what's left of the headers cap...
+    let response = minreq::get(server())
+        .with_max_headers_size(1024)
+        .send()
+        .unwrap();
+    assert_eq!(response.headers["x-trailer"].len(), 200);
+
+    // ...but not in the separate trailer cap.
+    let result = minreq::get(server())
+        .with_max_headers_size(1024)
+        .with_max_trailer_size(64)
+        .send();
+    assert!(matches!(result, Err(minreq::Error::HeadersOverflow)));
+
+    let response = minreq::get(server())
+        .with_max_trailer_size(256)
+        .send()
+        .unwrap();
+    assert_eq!(response.as_str().unwrap(), "hi");
+}
+
+#[test]
+fn test_force_close_delimited() {
+    let server = || {
+        raw_server(|stream, _| {
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nhello")
+                .unwrap();
+        })
+    };
+
+    let response = minreq::get(server()).send().unwrap();
+    assert_eq!(response.as_str().unwrap(), "he");
+
+    let response = minreq::get(server())
+        .with_force_close_delimited()
+        .send()
+        .unwrap();
+    assert_eq!(response.as_str().unwrap(), "hello");
+}
+
+#[test]
+fn test_strict_limits() {
+    use minreq::Error::*;
+    let limits = minreq::Limits::strict();
+    let send = |response: Vec<u8>| {
+        let server = raw_server(move |stream, _| {
+            stream.write_all(&response).ok();
+        });
+        minreq::get(server).with_limits(limits).send()
+    };
+
+    let ok = send(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok".to_vec());
+    assert_eq!(ok.unwrap().as_str().unwrap(), "ok");
+
+    let mut status_line = b"HTTP/1.1 200 ".to_vec();
+    status_line.resize(9000, b'K');
+    status_line.extend_from_slice(b"\r\n\r\n");
+    assert!(matches!(send(status_line), Err(StatusLineOverflow)));
+
+    let mut headers = b"HTTP/1.1 200 OK\r\n".to_vec();
+    for _ in 0..1000 {
+        headers.extend_from_slice(
+            b"X-Padding: ................................................................\r\n",
+        );
+    }
+    headers.extend_from_slice(b"\r\n");
+    assert!(matches!(send(headers), Err(HeadersOverflow)));
+
+    let mut trailers = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n".to_vec();
+    for _ in 0..300 {
+        trailers.extend_from_slice(
+            b"X-Padding: ................................................................\r\n",
+        );
+    }
+    trailers.extend_from_slice(b"\r\n");
+    assert!(matches!(send(trailers), Err(HeadersOverflow)));
+
+    let mut body = b"HTTP/1.1 200 OK\r\nContent-Length: 16777217\r\n\r\n".to_vec();
+    body.resize(body.len() + 16_777_217, b'.');
+    assert!(matches!(send(body), Err(BodyOverflow)));
+}
+
+#[test]
+fn test_extensions() {
+    #[derive(Debug, PartialEq)]
+    struct CorrelationId(String);
+    let server = || {
+        raw_server(|stream, head| {
+            assert!(!head.contains("abc-123"));
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok")
+                .unwrap();
+        })
+    };
+
+    let response = minreq::get(server())
+        .with_extension(CorrelationId("abc-123".to_string()))
+        .with_extension(7u32)
+        .send()
+        .unwrap();
+    assert_eq!(
+        response.extension::<CorrelationId>(),
+        Some(&CorrelationId("abc-123".to_string()))
+    );
+    assert_eq!(response.extension::<u32>(), Some(&7));
+    assert_eq!(response.extension::<u64>(), None);
+
+    let response = minreq::get(server())
+        .with_extension(1u32)
+        .with_extension(2u32)
+        .send_lazy()
+        .unwrap();
+    assert_eq!(response.extension::<u32>(), Some(&2));
+}
+
+#[test]
+#[cfg(feature = "encoding")]
+fn test_accept_charset() {
+    let server = raw_server(|stream, head| {
+        assert!(head.contains("\r\nAccept-Charset: ISO-8859-1, utf-8;q=0.5\r\n"));
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Type: text/plain; charset=ISO-8859-1\r\nContent-Length: 10\r\n\r\nCaf\xe9 cr\xe8me")
+            .unwrap();
+    });
+    let response = minreq::get(server)
+        .with_accept_charset(&["ISO-8859-1", "utf-8;q=0.5"])
+        .send()
+        .unwrap();
+    assert!(response.as_str().is_err());
+    assert_eq!(response.text(), "Caf\u{e9} cr\u{e8}me");
+}
+
+#[test]
+fn test_read_with_timeout() {
+    use std::time::Duration;
+    let server = raw_server(|stream, _| {
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\nab")
+            .unwrap();
+        std::thread::sleep(Duration::from_millis(500));
+        stream.write_all(b"cd").unwrap();
+    });
+    let mut response = minreq::get(server).send_lazy().unwrap();
+    let mut buf = [0; 16];
+
+    let n = response
+        .read_with_timeout(&mut buf, Duration::from_millis(100))
+        .unwrap();
+    assert_eq!(&buf[..n], b"ab");
+
+    let err = response
+        .read_with_timeout(&mut buf, Duration::from_millis(100))
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+
+    // The connection is still usable after the timeout.
+    let n = response
+        .read_with_timeout(&mut buf, Duration::from_secs(5))
+        .unwrap();
+    assert_eq!(&buf[..n], b"cd");
+    assert_eq!(response.read(&mut buf).unwrap(), 0);
+}
+
+#[test]
+fn test_transfer_encoding_values() {
+    let body = |response: &'static [u8]| {
+        let url = raw_server(move |stream, _| stream.write_all(response).unwrap());
+        minreq::get(url).send().unwrap().into_bytes()
+    };
+
+    // Identity is no coding at all, so Content-Length applies.
+    let identity =
+        b"HTTP/1.1 200 OK\r\nTransfer-Encoding: identity\r\nContent-Length: 2\r\n\r\nhi!!";
+    assert_eq!(body(identity), b"hi");
+
+    let identity_chunked = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: Identity, CHUNKED\r\n\r\n\
+        2\r\nhi\r\n0\r\n\r\n";
+    assert_eq!(body(identity_chunked), b"hi");
+
+    // The final coding decides the framing, the others are left for
+    // the caller to decode.
+    let gzip_chunked = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: gzip, chunked\r\n\r\n\
+        2\r\n\x1f\x8b\r\n0\r\n\r\n";
+    assert_eq!(body(gzip_chunked), b"\x1f\x8b");
+
+    let split_headers =
+        b"HTTP/1.1 200 OK\r\nTransfer-Encoding: gzip\r\nTransfer-Encoding: chunked\r\n\r\n\
+        2\r\n\x1f\x8b\r\n0\r\n\r\n";
+    assert_eq!(body(split_headers), b"\x1f\x8b");
+
+    // If chunked isn't the final coding, the body ends when the
+    // connection is closed, regardless of Content-Length.
+    let chunked_gzip =
+        b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked, gzip\r\nContent-Length: 1\r\n\r\n\
+        2\r\nhi\r\n0\r\n\r\n";
+    assert_eq!(body(chunked_gzip), b"2\r\nhi\r\n0\r\n\r\n");
+}
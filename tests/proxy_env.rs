@@ -0,0 +1,60 @@
+//! These tests set the proxy environment variables, which affect
+//! every request made in the process, so they are kept separate from
+//! the other tests.
+#![cfg(feature = "proxy")]
+
+extern crate minreq;
+#[allow(dead_code)]
+mod setup;
+
+use self::setup::*;
+use std::io::Write;
+
+fn ok_server() -> String {
+    raw_server(|stream, _| {
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok")
+            .unwrap();
+    })
+}
+
+#[test]
+fn test_env_proxy() {
+    // These share the environment variables, so they can't be run in
+    // parallel as separate tests.
+    without_env_proxy();
+    proxy_source();
+}
+
+fn without_env_proxy() {
+    // Nothing listens on port 1, so requests sent through the proxy fail.
+    std::env::set_var("http_proxy", "http://127.0.0.1:1");
+
+    assert!(minreq::get(ok_server()).send().is_err());
+
+    let response = minreq::get(ok_server()).without_env_proxy().send().unwrap();
+    assert_eq!(response.as_str().unwrap(), "ok");
+    assert_eq!(response.proxy_source(), None);
+
+    std::env::remove_var("http_proxy");
+}
+
+fn proxy_source() {
+    std::env::set_var("http_proxy", ok_server());
+    let response = minreq::get("http://example.com/").send().unwrap();
+    assert_eq!(response.as_str().unwrap(), "ok");
+    assert_eq!(response.proxy_source(), Some("http_proxy"));
+    std::env::remove_var("http_proxy");
+
+    std::env::set_var("ALL_PROXY", ok_server());
+    let response = minreq::get("http://example.com/").send_lazy().unwrap();
+    assert_eq!(response.proxy_source(), Some("ALL_PROXY"));
+    std::env::remove_var("ALL_PROXY");
+
+    let proxy = minreq::Proxy::new(ok_server()).unwrap();
+    let response = minreq::get("http://example.com/")
+        .with_proxy(proxy)
+        .send()
+        .unwrap();
+    assert_eq!(response.proxy_source(), None);
+}
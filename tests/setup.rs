@@ -1,6 +1,8 @@
 extern crate minreq;
 extern crate tiny_http;
 use self::tiny_http::{Header, Method, Response, Server, StatusCode};
+use std::io::Read;
+use std::net::{TcpListener, TcpStream};
 use std::str::FromStr;
 use std::sync::{Arc, Once};
 use std::thread;
@@ -98,6 +100,61 @@ pub fn setup() {
                         request.respond(response).ok();
                     }
 
+                    Method::Post if url == "/redirect-301" => {
+                        let response = Response::empty(301).with_header(
+                            Header::from_bytes(&b"Location"[..], &b"http://localhost:35562/a"[..])
+                                .unwrap(),
+                        );
+                        request.respond(response).ok();
+                    }
+
+                    Method::Post if url == "/redirect-307" || url == "/redirect-308" => {
+                        let status = if url == "/redirect-307" { 307 } else { 308 };
+                        let response = Response::empty(status).with_header(
+                            Header::from_bytes(&b"Location"[..], &b"/echo"[..]).unwrap(),
+                        );
+                        request.respond(response).ok();
+                    }
+
+                    Method::Get if url == "/basic-auth" => {
+                        // "user:pass" in base64
+                        let authorized = headers.iter().any(|header| {
+                            header.field.as_str() == "Authorization"
+                                && header.value.as_str() == "Basic dXNlcjpwYXNz"
+                        });
+                        let response = if authorized {
+                            Response::from_string("Authenticated.")
+                        } else {
+                            Response::from_string("Unauthorized.")
+                                .with_status_code(401)
+                                .with_header(
+                                    Header::from_bytes(
+                                        &b"WWW-Authenticate"[..],
+                                        &b"Basic realm=\"test\""[..],
+                                    )
+                                    .unwrap(),
+                                )
+                        };
+                        request.respond(response).ok();
+                    }
+
+                    Method::Get if url == "/multi_cookie" => {
+                        let response = Response::empty(200)
+                            .with_header(
+                                Header::from_bytes(&b"Set-Cookie"[..], &b"a=1"[..]).unwrap(),
+                            )
+                            .with_header(
+                                Header::from_bytes(&b"Set-Cookie"[..], &b"b=2"[..]).unwrap(),
+                            );
+                        request.respond(response).ok();
+                    }
+
+                    Method::Get if url == "/redirect-no-location" => {
+                        let response =
+                            Response::from_string("Nowhere to go.").with_status_code(302);
+                        request.respond(response).ok();
+                    }
+
                     Method::Get if url == "/infiniteredirect" => {
                         let response = Response::empty(301).with_header(
                             Header::from_bytes(
@@ -171,6 +228,91 @@ pub fn setup() {
     });
 }
 
+/// Starts a server on a random port, which accepts one connection,
+/// reads the request head, and then passes the connection to
+/// `handler`. Useful for sending responses tiny_http wouldn't send.
+/// Returns the base url of the server.
+pub fn raw_server<F>(handler: F) -> String
+where
+    F: FnOnce(&mut TcpStream, &str) + Send + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        if let Some(head) = read_head(&mut stream) {
+            handler(&mut stream, &head);
+        }
+    });
+    format!("http://127.0.0.1:{}", port)
+}
+
+/// Reads from `stream` until the end of a request head, or returns
+/// None if the stream ends before that.
+fn read_head<R: Read>(stream: &mut R) -> Option<String> {
+    let mut head = Vec::new();
+    let mut byte = [0];
+    while !head.ends_with(b"\r\n\r\n") {
+        match stream.read(&mut byte) {
+            Ok(1) => head.push(byte[0]),
+            _ => return None,
+        }
+    }
+    Some(String::from_utf8_lossy(&head).into_owned())
+}
+
+/// Returns a rustls configuration which trusts the test certificate
+/// authority in `tests/certs`, which has issued the certificate for
+/// `localhost` and `127.0.0.1` used by [`tls_respond`].
+#[cfg(feature = "rustls")]
+pub fn tls_client_config() -> Arc<rustls::ClientConfig> {
+    let ca = rustls::Certificate(include_bytes!("certs/ca.der").to_vec());
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(&ca).unwrap();
+    let config = rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Arc::new(config)
+}
+
+/// Returns a rustls server configuration for `localhost` and
+/// `127.0.0.1`, using the certificate in `tests/certs`.
+#[cfg(feature = "rustls")]
+pub fn tls_server_config() -> rustls::ServerConfig {
+    let cert = rustls::Certificate(include_bytes!("certs/localhost.der").to_vec());
+    let key = rustls::PrivateKey(include_bytes!("certs/localhost.key.der").to_vec());
+    rustls::ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(vec![cert], key)
+        .unwrap()
+}
+
+/// Acts as the server side of a TLS session for `localhost` over
+/// `stream`, reads one request head and writes `response` as the
+/// answer. Returns the request head.
+#[cfg(feature = "rustls")]
+pub fn tls_respond(stream: &mut TcpStream, response: &[u8]) -> String {
+    tls_respond_with(Arc::new(tls_server_config()), stream, response)
+}
+
+/// Like [`tls_respond`], with the given server configuration.
+#[cfg(feature = "rustls")]
+pub fn tls_respond_with(
+    config: Arc<rustls::ServerConfig>,
+    stream: &mut TcpStream,
+    response: &[u8],
+) -> String {
+    use std::io::Write;
+    let mut connection = rustls::ServerConnection::new(config).unwrap();
+    let mut tls = rustls::Stream::new(&mut connection, stream);
+    let head = read_head(&mut tls).unwrap();
+    tls.write_all(response).unwrap();
+    tls.flush().unwrap();
+    head
+}
+
 pub fn url(req: &str) -> String {
     format!("http://localhost:35562{}", req)
 }